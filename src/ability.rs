@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+use bevy_ggrs::*;
+
+use crate::health::Health;
+use crate::pickup::PowerUps;
+use crate::smoke::{spawn_smoke, SmokeAssets, SMOKE_DURATION};
+use crate::{Player, TankInputs};
+
+/// Input bits for triggering a tank's first and second ability
+pub const INPUT_ABILITY_1: u64 = 1 << 12;
+pub const INPUT_ABILITY_2: u64 = 1 << 13;
+pub const ABILITY_INPUTS: [u64; 2] = [INPUT_ABILITY_1, INPUT_ABILITY_2];
+/// Keys that trigger the local player's abilities, in slot order
+const ABILITY_KEYS: [&str; 2] = ["F", "G"];
+
+/// Hit points per second restored while the repair ability runs
+const REPAIR_RATE: f32 = 8.;
+
+/// Active abilities a tank class can bring
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AbilityKind {
+    /// lays a smoke cloud around the tank
+    Smoke,
+    /// faster top speed and acceleration for a while
+    Boost,
+    /// no damage taken for a while
+    Shield,
+    /// the crew patches the tank up over a few seconds
+    Repair,
+}
+
+/// How an ability behaves
+pub struct AbilityDef {
+    pub name: &'static str,
+    /// seconds between uses
+    pub cooldown: f32,
+    /// seconds the effect lasts
+    pub duration: f32,
+}
+
+impl AbilityKind {
+    pub fn def(self) -> AbilityDef {
+        match self {
+            AbilityKind::Smoke => AbilityDef {
+                name: "Smoke",
+                cooldown: 30.,
+                duration: SMOKE_DURATION,
+            },
+            AbilityKind::Boost => AbilityDef {
+                name: "Boost",
+                cooldown: 20.,
+                duration: 4.,
+            },
+            AbilityKind::Shield => AbilityDef {
+                name: "Shield",
+                cooldown: 40.,
+                duration: 3.,
+            },
+            AbilityKind::Repair => AbilityDef {
+                name: "Repair",
+                cooldown: 45.,
+                duration: 5.,
+            },
+        }
+    }
+}
+
+/// One of a tank's abilities and its timers
+#[derive(Clone)]
+pub struct AbilitySlot {
+    pub kind: AbilityKind,
+    /// seconds until the ability can be used again
+    pub cooldown: f32,
+    /// seconds left on the running effect, zero when it isn't running
+    pub active: f32,
+}
+
+impl AbilitySlot {
+    /// Counts the timers down by `delta` seconds, returns how long the effect ran for this frame
+    pub fn tick(&mut self, delta: f32) -> f32 {
+        self.cooldown = (self.cooldown - delta).max(0.);
+        let ran = delta.min(self.active);
+        self.active = (self.active - delta).max(0.);
+        ran
+    }
+
+    /// Starts the ability if it is off cooldown, returns whether it went off
+    pub fn trigger(&mut self) -> bool {
+        if self.cooldown > 0. {
+            return false;
+        }
+        let def = self.kind.def();
+        self.cooldown = def.cooldown;
+        self.active = def.duration;
+        true
+    }
+}
+
+/// A tank's abilities, rolled back so every peer agrees on when they were used
+#[derive(Component, Clone)]
+pub struct Abilities {
+    pub slots: Vec<AbilitySlot>,
+}
+
+impl Abilities {
+    pub fn new(kinds: &[AbilityKind]) -> Self {
+        Self {
+            slots: kinds
+                .iter()
+                .map(|&kind| AbilitySlot {
+                    kind,
+                    cooldown: 0.,
+                    active: 0.,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Ticks ability timers and triggers the abilities each player asks for
+pub fn use_abilities(
+    mut commands: Commands,
+    inputs: Res<TankInputs>,
+    time: Res<Time>,
+    smoke_assets: Res<SmokeAssets>,
+    mut player_query: Query<(&Player, &Transform, &mut Abilities, &mut PowerUps, &mut Health)>,
+) {
+    let delta = time.delta_seconds();
+
+    for (ship, ship_transform, mut abilities, mut power_ups, mut health) in &mut player_query {
+        let input = inputs[ship.handle];
+
+        for (slot, input_bit) in abilities.slots.iter_mut().zip(ABILITY_INPUTS) {
+            let ran = slot.tick(delta);
+            if slot.kind == AbilityKind::Repair && ran > 0. {
+                health.current = (health.current + REPAIR_RATE * ran).min(health.max);
+            }
+
+            if input & input_bit == 0 || health.current <= 0. || !slot.trigger() {
+                continue;
+            }
+
+            let def = slot.kind.def();
+
+            // boost and shield share their effect timers with the pickups of the same name
+            match slot.kind {
+                AbilityKind::Smoke => {
+                    spawn_smoke(&mut commands, &smoke_assets, ship.handle, ship_transform.translation.xy())
+                }
+                AbilityKind::Boost => power_ups.speed = power_ups.speed.max(def.duration),
+                AbilityKind::Shield => power_ups.shield = power_ups.shield.max(def.duration),
+                AbilityKind::Repair => {}
+            }
+        }
+    }
+}
+
+/// Text showing the local player's abilities and their cooldowns
+#[derive(Component)]
+pub struct AbilityHud;
+
+pub fn spawn_ability_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        AbilityHud,
+    ));
+}
+
+pub fn update_ability_hud(
+    local_players: Option<Res<LocalPlayers>>,
+    ability_query: Query<(&Player, &Abilities)>,
+    mut hud_query: Query<&mut Text, With<AbilityHud>>,
+) {
+    let local_handles = local_players.map(|local_players| local_players.0.clone()).unwrap_or_default();
+
+    let mut lines = Vec::new();
+    for (ship, abilities) in &ability_query {
+        if !local_handles.contains(&ship.handle) {
+            continue;
+        }
+        for (slot, key) in abilities.slots.iter().zip(ABILITY_KEYS) {
+            let status = if slot.active > 0. {
+                format!("active {:.0}s", slot.active.ceil())
+            } else if slot.cooldown > 0. {
+                format!("{:.0}s", slot.cooldown.ceil())
+            } else {
+                "ready".to_string()
+            };
+            lines.push(format!("{key} {}: {status}", slot.kind.def().name));
+        }
+    }
+
+    for mut text in &mut hud_query {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot(kind: AbilityKind) -> AbilitySlot {
+        Abilities::new(&[kind]).slots.remove(0)
+    }
+
+    #[test]
+    fn abilities_start_ready() {
+        let mut boost = slot(AbilityKind::Boost);
+        assert!(boost.trigger());
+        assert_eq!(boost.active, AbilityKind::Boost.def().duration);
+        assert_eq!(boost.cooldown, AbilityKind::Boost.def().cooldown);
+    }
+
+    #[test]
+    fn abilities_wait_out_their_cooldown() {
+        let mut shield = slot(AbilityKind::Shield);
+        let def = AbilityKind::Shield.def();
+        assert!(shield.trigger());
+
+        shield.tick(def.cooldown - 1.);
+        assert!(!shield.trigger());
+        assert_eq!(shield.cooldown, 1., "a refused trigger leaves the cooldown alone");
+
+        shield.tick(1.);
+        assert!(shield.trigger());
+    }
+
+    #[test]
+    fn effects_end_after_their_duration() {
+        let mut repair = slot(AbilityKind::Repair);
+        let def = AbilityKind::Repair.def();
+        assert_eq!(repair.tick(1.), 0., "nothing runs before the ability is used");
+
+        repair.trigger();
+        assert_eq!(repair.tick(def.duration - 1.), def.duration - 1.);
+        // the last frame only counts the part of it the effect was still running
+        assert_eq!(repair.tick(2.), 1.);
+        assert_eq!(repair.active, 0.);
+        assert_eq!(repair.tick(1.), 0.);
+    }
+}
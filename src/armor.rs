@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+
+use crate::collision::OrientedRect;
+
+/// Shots striking a face further than this from head on glance off, in radians
+const RICOCHET_ANGLE: f32 = 70. * std::f32::consts::PI / 180.;
+
+/// Armor thickness of a hull's faces in millimetres
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Armor {
+    pub front: f32,
+    pub side: f32,
+    pub rear: f32,
+}
+
+/// Which face of the hull a shot struck
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HullFace {
+    Front,
+    Side,
+    Rear,
+}
+
+/// How a shot met the armor
+pub struct ArmorHit {
+    /// angle between the shot and the face's normal in radians, zero for a head on hit
+    pub impact_angle: f32,
+    /// millimetres of armor the shot has to go through at this angle
+    pub effective_thickness: f32,
+}
+
+/// What happened to a shell that struck armor
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HitOutcome {
+    /// went through and deals its full damage
+    Penetrated,
+    /// stopped by the armor without doing any damage
+    NotPenetrated,
+    /// glanced off and carries on in a new direction
+    Ricochet,
+}
+
+impl ArmorHit {
+    /// Compares a shell's penetration in millimetres against the armor it has to go through
+    pub fn outcome(&self, penetration: f32) -> HitOutcome {
+        if self.impact_angle > RICOCHET_ANGLE {
+            HitOutcome::Ricochet
+        } else if penetration >= self.effective_thickness {
+            HitOutcome::Penetrated
+        } else {
+            HitOutcome::NotPenetrated
+        }
+    }
+}
+
+impl Armor {
+    pub fn thickness(&self, face: HullFace) -> f32 {
+        match face {
+            HullFace::Front => self.front,
+            HullFace::Side => self.side,
+            HullFace::Rear => self.rear,
+        }
+    }
+
+    /// Resolves a shot travelling along `direction` that struck the face of `hull` with the world space `normal`
+    ///
+    /// Shots that aren't travelling at all, like a mine going off under a tank, strike the belly where there is no
+    /// armor to speak of.
+    pub fn hit(&self, hull: &OrientedRect, normal: Vec2, direction: Vec2) -> ArmorHit {
+        if direction == Vec2::ZERO {
+            return ArmorHit {
+                impact_angle: 0.,
+                effective_thickness: 0.,
+            };
+        }
+
+        // the hull faces forward along its local Y axis
+        let forward = normal.dot(hull.y_axis());
+        let face = if forward > 0.5 {
+            HullFace::Front
+        } else if forward < -0.5 {
+            HullFace::Rear
+        } else {
+            HullFace::Side
+        };
+
+        let cos_angle = (-direction.normalize_or_zero()).dot(normal).clamp(0., 1.);
+        let impact_angle = cos_angle.acos();
+        // sloped armor is thicker along the path of the shot
+        let effective_thickness = self.thickness(face) / cos_angle.max(f32::EPSILON);
+
+        ArmorHit {
+            impact_angle,
+            effective_thickness,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weapon::WeaponKind;
+
+    const ARMOR: Armor = Armor {
+        front: 100.,
+        side: 60.,
+        rear: 30.,
+    };
+
+    /// A hull at the origin facing up the Y axis
+    fn hull() -> OrientedRect {
+        OrientedRect::axis_aligned(Vec2::ZERO, Vec2::new(1., 2.))
+    }
+
+    #[test]
+    fn head_on_hit_goes_through_the_face_thickness() {
+        let hit = ARMOR.hit(&hull(), Vec2::Y, -Vec2::Y);
+        assert_eq!(hit.impact_angle, 0.);
+        assert_eq!(hit.effective_thickness, ARMOR.front);
+        assert_eq!(hit.outcome(120.), HitOutcome::Penetrated);
+        assert_eq!(hit.outcome(80.), HitOutcome::NotPenetrated);
+    }
+
+    #[test]
+    fn rear_is_thinner_than_the_front() {
+        let hit = ARMOR.hit(&hull(), -Vec2::Y, Vec2::Y);
+        assert_eq!(hit.effective_thickness, ARMOR.rear);
+        assert_eq!(hit.outcome(40.), HitOutcome::Penetrated);
+    }
+
+    #[test]
+    fn sloped_hit_meets_thicker_armor() {
+        // 45 degrees off the side face's normal
+        let hit = ARMOR.hit(&hull(), Vec2::X, -Vec2::new(1., 1.).normalize());
+        assert!((hit.effective_thickness - ARMOR.side * std::f32::consts::SQRT_2).abs() < 0.01);
+        assert_eq!(hit.outcome(ARMOR.side), HitOutcome::NotPenetrated);
+    }
+
+    #[test]
+    fn glancing_hit_ricochets() {
+        let hit = ARMOR.hit(&hull(), Vec2::Y, Vec2::new(1., -0.1));
+        assert_eq!(hit.outcome(1000.), HitOutcome::Ricochet);
+    }
+
+    #[test]
+    fn mine_damages_the_tank_that_triggers_it() {
+        // a mine sits still, so the tank driving onto it starts inside its hull with no face struck
+        let mine = WeaponKind::Mines.def();
+        let hit = ARMOR.hit(&hull(), Vec2::ZERO, Vec2::ZERO);
+        assert_eq!(hit.outcome(mine.penetration), HitOutcome::Penetrated);
+    }
+}
@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+use bevy_ggrs::*;
+
+use crate::obstacle::{Destructible, Obstacle};
+use crate::{Player, TankInputs, HULL_SIZE};
+
+/// Input bit for dropping a barricade behind the hull
+pub const INPUT_BARRICADE: u64 = 1 << 11;
+
+/// Length and thickness of a barricade in meters
+const BARRICADE_SIZE: Vec2 = Vec2::new(4., 1.);
+/// Hit points of a freshly placed barricade
+const BARRICADE_HEALTH: f32 = 120.;
+/// Seconds between placing barricades
+const BARRICADE_COOLDOWN: f32 = 10.;
+/// Most barricades one tank can have standing at once
+const MAX_BARRICADES: usize = 2;
+/// Gap between the back of the hull and the barricade in meters
+const BARRICADE_OFFSET: f32 = 1.;
+
+/// A destructible block a tank placed, rolled back so every peer agrees on where it stands
+#[derive(Component, Clone)]
+pub struct Barricade {
+    /// handle of the player who placed it
+    pub owner: usize,
+}
+
+/// A tank's barricade cooldown, rolled back with the rest of the tank
+#[derive(Component, Clone, Default)]
+pub struct BarricadeLayer {
+    /// seconds until the next barricade can be placed
+    pub cooldown: f32,
+}
+
+impl BarricadeLayer {
+    /// Whether another barricade can go down with `standing` of this tank's barricades already up
+    pub fn ready(&self, standing: usize) -> bool {
+        self.cooldown <= 0. && standing < MAX_BARRICADES
+    }
+}
+
+/// Drops a barricade behind every tank that asks for one, as long as it is off cooldown and under its limit
+pub fn deploy_barricades(
+    mut commands: Commands,
+    inputs: Res<TankInputs>,
+    time: Res<Time>,
+    mut player_query: Query<(&Player, &Transform, &mut BarricadeLayer)>,
+    barricade_query: Query<&Barricade>,
+) {
+    for (ship, ship_transform, mut layer) in &mut player_query {
+        let input = inputs[ship.handle];
+
+        layer.cooldown = (layer.cooldown - time.delta_seconds()).max(0.);
+        if input & INPUT_BARRICADE == 0 {
+            continue;
+        }
+        let standing = barricade_query
+            .iter()
+            .filter(|barricade| barricade.owner == ship.handle)
+            .count();
+        if !layer.ready(standing) {
+            continue;
+        }
+        layer.cooldown = BARRICADE_COOLDOWN;
+
+        // obstacles are axis aligned, so the barricade is laid across whichever axis the hull is closest to facing along
+        let forward = (ship_transform.rotation * Vec3::Y).xy();
+        let size = if forward.x.abs() > forward.y.abs() {
+            BARRICADE_SIZE.yx()
+        } else {
+            BARRICADE_SIZE
+        };
+        let center = ship_transform.translation.xy()
+            - forward * (HULL_SIZE.y / 2. + BARRICADE_OFFSET + BARRICADE_SIZE.y / 2.);
+
+        commands
+            .spawn((
+                SpriteBundle {
+                    transform: Transform::from_translation(Vec3::from((center, 50.))),
+                    sprite: Sprite {
+                        color: Color::rgb(0.6, 0.5, 0.3),
+                        custom_size: Some(size),
+                        ..default()
+                    },
+                    ..default()
+                },
+                Obstacle {
+                    half_extents: size / 2.,
+                },
+                Destructible {
+                    health: BARRICADE_HEALTH,
+                    max_health: BARRICADE_HEALTH,
+                },
+                Barricade { owner: ship.handle },
+            ))
+            .add_rollback();
+    }
+}
+
+/// Clears away barricades that have been shot down so their owner can place new ones
+pub fn remove_destroyed_barricades(
+    mut commands: Commands,
+    barricade_query: Query<(Entity, &Destructible), With<Barricade>>,
+) {
+    for (entity, destructible) in &barricade_query {
+        if destructible.destroyed() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn barricades_go_down_up_to_the_limit() {
+        let layer = BarricadeLayer::default();
+        for standing in 0..MAX_BARRICADES {
+            assert!(layer.ready(standing));
+        }
+        assert!(!layer.ready(MAX_BARRICADES));
+        assert!(!layer.ready(MAX_BARRICADES + 1));
+    }
+
+    #[test]
+    fn barricades_wait_for_the_cooldown() {
+        let layer = BarricadeLayer {
+            cooldown: BARRICADE_COOLDOWN,
+        };
+        assert!(!layer.ready(0));
+    }
+}
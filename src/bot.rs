@@ -0,0 +1,525 @@
+use bevy::prelude::*;
+use std::f32::consts::{FRAC_PI_3, TAU};
+
+use crate::ability::{Abilities, AbilityKind, ABILITY_INPUTS};
+use crate::collision::OrientedRect;
+use crate::fog::{line_of_sight, standing_obstacles};
+use crate::health::Health;
+use crate::nav::{NavGrid, NAV_CELL_SIZE};
+use crate::obstacle::{Destructible, Obstacle};
+use crate::rng::RollbackRng;
+use crate::tank_class::TankClass;
+use crate::weapon::{Armament, WeaponKind};
+use crate::{
+    encode_aim, Player, TankInputs, Team, Turret, Velocity, HULL_SIZE, INPUT_FIRE, INPUT_FORWARD,
+    INPUT_LEFT, INPUT_RIGHT,
+};
+
+/// Seconds between bots re-rolling their aim error and strafe direction
+const AIM_REROLL_TIME: f32 = 1.;
+/// How far either side of its preferred range a bot is happy to fight from in meters
+const RANGE_SLACK: f32 = 5.;
+/// How close a bot has to get to where it is going to count as there in meters
+const ARRIVE_DISTANCE: f32 = 3.;
+/// How far ahead of the hull a bot looks for obstacles in meters
+const FEELER_LENGTH: f32 = 6.;
+/// Angle either side of straight ahead a bot checks for a way around an obstacle in radians
+const FEELER_ANGLE: f32 = 0.6;
+/// Largest angle off its heading a bot still drives forward at instead of turning on the spot in radians
+const DRIVE_ANGLE: f32 = FRAC_PI_3;
+/// Smallest angle off its heading a bot bothers steering for in radians
+const STEER_DEADBAND: f32 = 0.1;
+/// How far away a bot looks for cover in meters
+const COVER_SEARCH: f32 = 40.;
+/// How far behind an obstacle a bot parks when taking cover in meters
+const COVER_GAP: f32 = 3.;
+/// Reload time left that sends a bot into cover in seconds
+const RELOAD_COVER_TIME: f32 = 1.;
+/// How far a bot drives sideways while strafing a target in meters
+const STRAFE_DISTANCE: f32 = 6.;
+/// Seconds between a bot planning a fresh route to the same destination
+const REPATH_TIME: f32 = 2.;
+/// How far a bot's destination has to move before it plans a fresh route straight away in meters
+const REPATH_DISTANCE: f32 = 5.;
+/// How close a bot has to get to a waypoint on its route before heading for the next one in meters
+const WAYPOINT_REACHED: f32 = NAV_CELL_SIZE * 1.5;
+
+/// How good a bot is
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+/// How a bot of a difficulty plays
+struct DifficultyDef {
+    /// meters a bot's aim strays from where it wants to shoot
+    aim_error: f32,
+    /// share of a moving target's travel during the shell's flight that the bot aims ahead by
+    lead: f32,
+    /// distance the bot likes to fight from in meters
+    preferred_range: f32,
+    /// how far off its aim the turret can be for the bot to fire in radians
+    fire_tolerance: f32,
+    /// whether the bot hides behind obstacles while reloading or hurt
+    uses_cover: bool,
+    /// share of its health below which the bot falls back and uses its defensive abilities
+    retreat_health: f32,
+}
+
+impl DifficultyDef {
+    /// Whether a bot wants to use an ability right now, `target_distance` is how far off the enemy it is fighting is
+    fn wants_ability(&self, kind: AbilityKind, hurt: bool, target_distance: Option<f32>) -> bool {
+        match kind {
+            // smoke covers a retreat from whoever is shooting at the bot
+            AbilityKind::Smoke => hurt && target_distance.is_some(),
+            // a burst of speed gets the bot out of trouble or closes in on a target that is out of range
+            AbilityKind::Boost => {
+                target_distance.is_some_and(|distance| hurt || distance > self.preferred_range + RANGE_SLACK)
+            }
+            // the shield is saved for trading shots while hurt
+            AbilityKind::Shield => {
+                hurt && target_distance.is_some_and(|distance| distance <= self.preferred_range + RANGE_SLACK)
+            }
+            // patching up is only worth it with nobody to fight
+            AbilityKind::Repair => hurt && target_distance.is_none(),
+        }
+    }
+}
+
+impl Difficulty {
+    fn def(self) -> DifficultyDef {
+        match self {
+            Difficulty::Easy => DifficultyDef {
+                aim_error: 4.,
+                lead: 0.,
+                preferred_range: 40.,
+                fire_tolerance: f32::to_radians(10.),
+                uses_cover: false,
+                retreat_health: 0.,
+            },
+            Difficulty::Normal => DifficultyDef {
+                aim_error: 1.5,
+                lead: 0.6,
+                preferred_range: 35.,
+                fire_tolerance: f32::to_radians(6.),
+                uses_cover: true,
+                retreat_health: 0.35,
+            },
+            Difficulty::Hard => DifficultyDef {
+                aim_error: 0.4,
+                lead: 1.,
+                preferred_range: 30.,
+                fire_tolerance: f32::to_radians(3.),
+                uses_cover: true,
+                retreat_health: 0.5,
+            },
+        }
+    }
+}
+
+/// A tank driven by the computer, rolled back so every peer's bots make the same decisions
+#[derive(Component, Clone)]
+pub struct Bot {
+    pub difficulty: Difficulty,
+    /// handle of the tank the bot is fighting
+    pub target: Option<usize>,
+    /// where the bot drives to while it has nobody to fight
+    pub waypoint: Option<Vec2>,
+    /// how far off the bot's aim is, re-rolled every so often
+    pub aim_offset: Vec2,
+    /// seconds until the aim error and strafe direction are re-rolled
+    pub aim_timer: f32,
+    /// which way the bot strafes around its target, 1 or -1
+    pub strafe: f32,
+    /// waypoints of the route the bot is following, the next one last
+    pub path: Vec<Vec2>,
+    /// destination the route was planned for
+    pub path_goal: Option<Vec2>,
+    /// seconds until the route is planned again
+    pub repath_timer: f32,
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty) -> Self {
+        Self {
+            difficulty,
+            target: None,
+            waypoint: None,
+            aim_offset: Vec2::ZERO,
+            aim_timer: 0.,
+            strafe: 1.,
+            path: Vec::new(),
+            path_goal: None,
+            repath_timer: 0.,
+        }
+    }
+}
+
+/// What a bot knows about a tank
+struct TankView {
+    handle: usize,
+    team: Team,
+    position: Vec2,
+    forward: Vec2,
+    velocity: Vec2,
+    health: f32,
+}
+
+/// Works out every bot's input for this frame, encoded exactly like a player's
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn drive_bots(
+    time: Res<Time>,
+    mut rng: ResMut<RollbackRng>,
+    mut tank_inputs: ResMut<TankInputs>,
+    nav_grid: Res<NavGrid>,
+    tank_query: Query<(&Player, &Team, &Transform, &Velocity, &Health)>,
+    mut bot_query: Query<(&Player, &mut Bot, &Armament, &TankClass, &Abilities)>,
+    turret_query: Query<(&Turret, &Transform)>,
+    obstacle_query: Query<(&Obstacle, &Transform, Option<&Destructible>)>,
+) {
+    let tanks: Vec<TankView> = tank_query
+        .iter()
+        .map(|(ship, team, transform, velocity, health)| TankView {
+            handle: ship.handle,
+            team: *team,
+            position: transform.translation.xy(),
+            forward: (transform.rotation * Vec3::Y).xy(),
+            velocity: velocity.0,
+            health: health.current / health.max,
+        })
+        .collect();
+    let obstacles = standing_obstacles(&obstacle_query);
+    // obstacles are grown by half a hull so steering keeps the whole tank clear of them
+    let grown: Vec<OrientedRect> = obstacles
+        .iter()
+        .map(|obstacle| OrientedRect {
+            half_extents: obstacle.half_extents + HULL_SIZE.x / 2.,
+            ..*obstacle
+        })
+        .collect();
+
+    // bots draw from the shared random numbers in handle order so every peer hands out the same numbers
+    let mut bots: Vec<_> = bot_query.iter_mut().collect();
+    bots.sort_by_key(|(ship, _, _, _, _)| ship.handle);
+
+    for (ship, bot, armament, class, abilities) in bots {
+        let bot = bot.into_inner();
+        let Some(me) = tanks.iter().find(|tank| tank.handle == ship.handle) else {
+            continue;
+        };
+        if me.health <= 0. {
+            tank_inputs.set(ship.handle, 0);
+            continue;
+        }
+        let def = bot.difficulty.def();
+
+        let target = choose_target(me, bot.target, ship.view_range, &tanks, &obstacles);
+        bot.target = target.map(|tank| tank.handle);
+
+        bot.aim_timer -= time.delta_seconds();
+        if bot.aim_timer <= 0. {
+            bot.aim_timer = AIM_REROLL_TIME;
+            bot.aim_offset = Vec2::from_angle(rng.range(0., TAU)) * rng.range(0., def.aim_error);
+            if rng.next_f32() < 0.3 {
+                bot.strafe = -bot.strafe;
+            }
+        }
+
+        let weapon_kind = if *class == TankClass::Spg {
+            WeaponKind::Artillery
+        } else {
+            WeaponKind::Cannon
+        };
+        let weapon = weapon_kind.def();
+        let hurt = me.health < def.retreat_health;
+
+        let mut input = weapon_kind.to_input();
+
+        let (destination, aim) = match target {
+            Some(target) => {
+                let to_target = target.position - me.position;
+                let distance = to_target.length();
+
+                let reloading = armament.cooldowns[weapon_kind.slot()] > RELOAD_COVER_TIME;
+                let cover = (def.uses_cover && (hurt || reloading))
+                    .then(|| cover_point(me.position, target.position, &obstacles))
+                    .flatten();
+                let destination = if let Some(cover) = cover {
+                    cover
+                } else if distance > def.preferred_range + RANGE_SLACK {
+                    target.position
+                } else if distance < def.preferred_range - RANGE_SLACK {
+                    me.position - to_target
+                } else {
+                    me.position + to_target.normalize_or_zero().perp() * bot.strafe * STRAFE_DISTANCE
+                };
+
+                // aim where the target will be when the shell gets there
+                let flight_time = distance / weapon.projectile_speed.max(f32::EPSILON);
+                let aim = target.position + target.velocity * flight_time * def.lead + bot.aim_offset;
+
+                let turret_aligned = turret_query
+                    .iter()
+                    .find(|(turret, _)| turret.handle == ship.handle)
+                    .is_some_and(|(_, turret_transform)| {
+                        let turret_forward = (turret_transform.rotation * Vec3::Y).xy();
+                        let to_aim = aim - turret_transform.translation.xy();
+                        turret_forward.angle_between(to_aim).abs() <= def.fire_tolerance
+                    });
+                if turret_aligned && distance <= weapon.range {
+                    input |= INPUT_FIRE;
+                }
+                (destination, aim)
+            }
+            None => {
+                // wander between random points until somebody turns up
+                let waypoint = bot
+                    .waypoint
+                    .filter(|waypoint| waypoint.distance(me.position) > ARRIVE_DISTANCE)
+                    .unwrap_or_else(|| {
+                        let extents = nav_grid.bounds() * 0.4;
+                        Vec2::new(rng.range(-extents.x, extents.x), rng.range(-extents.y, extents.y))
+                    });
+                bot.waypoint = Some(waypoint);
+                (waypoint, me.position + me.forward * 10.)
+            }
+        };
+
+        // one ability at a time, so the bot doesn't spend everything it has on the same moment
+        let target_distance = target.map(|target| me.position.distance(target.position));
+        if let Some((_, input_bit)) = abilities.slots.iter().zip(ABILITY_INPUTS).find(|(slot, _)| {
+            slot.cooldown <= 0. && slot.active <= 0. && def.wants_ability(slot.kind, hurt, target_distance)
+        }) {
+            input |= input_bit;
+        }
+        input |= steer(me, follow_path(bot, me, destination, &grown, &nav_grid, time.delta_seconds()), &grown);
+        input |= encode_aim(aim);
+        tank_inputs.set(ship.handle, input);
+    }
+}
+
+/// Picks the enemy a bot fights, it sticks with its current target while it can still be seen and otherwise goes
+/// for the closest enemy in sight
+fn choose_target<'a>(
+    me: &TankView,
+    current: Option<usize>,
+    view_range: f32,
+    tanks: &'a [TankView],
+    obstacles: &[OrientedRect],
+) -> Option<&'a TankView> {
+    let can_see = |tank: &TankView| {
+        tank.health > 0.
+            && tank.team != me.team
+            && me.position.distance(tank.position) <= view_range
+            && line_of_sight(me.position, tank.position, obstacles, &[])
+    };
+
+    current
+        .and_then(|handle| tanks.iter().find(|tank| tank.handle == handle))
+        .filter(|tank| can_see(tank))
+        .or_else(|| {
+            tanks
+                .iter()
+                .filter(|tank| can_see(tank))
+                .min_by(|a, b| me.position.distance(a.position).total_cmp(&me.position.distance(b.position)))
+        })
+}
+
+/// Where the bot should head for right now on its way to a destination
+///
+/// Destinations in plain sight are driven to directly, otherwise the bot follows a route from the navigation grid
+/// and plans it again when the destination moves or every so often.
+fn follow_path(
+    bot: &mut Bot,
+    me: &TankView,
+    destination: Vec2,
+    grown: &[OrientedRect],
+    nav_grid: &NavGrid,
+    delta: f32,
+) -> Vec2 {
+    if line_of_sight(me.position, destination, grown, &[]) {
+        bot.path.clear();
+        bot.path_goal = None;
+        return destination;
+    }
+
+    bot.repath_timer -= delta;
+    let moved = bot.path_goal.is_none_or(|goal| goal.distance(destination) > REPATH_DISTANCE);
+    if moved || bot.repath_timer <= 0. {
+        bot.path = nav_grid.find_path(me.position, destination).unwrap_or_default();
+        bot.path_goal = Some(destination);
+        bot.repath_timer = REPATH_TIME;
+    }
+
+    while bot.path.last().is_some_and(|waypoint| waypoint.distance(me.position) <= WAYPOINT_REACHED) {
+        bot.path.pop();
+    }
+    bot.path.last().copied().unwrap_or(destination)
+}
+
+/// Turns and drives towards a destination, swerving around obstacles in the way
+fn steer(me: &TankView, destination: Vec2, grown: &[OrientedRect]) -> u64 {
+    let to_destination = destination - me.position;
+    if to_destination.length() <= ARRIVE_DISTANCE {
+        return 0;
+    }
+
+    let clear = |direction: Vec2| line_of_sight(me.position, me.position + direction * FEELER_LENGTH, grown, &[]);
+
+    let mut desired = to_destination.normalize();
+    if !clear(me.forward) {
+        let left = Vec2::from_angle(FEELER_ANGLE).rotate(me.forward);
+        let right = Vec2::from_angle(-FEELER_ANGLE).rotate(me.forward);
+        desired = if clear(left) {
+            left
+        } else if clear(right) {
+            right
+        } else {
+            // boxed in, turn on the spot until a way out opens up
+            me.forward.perp()
+        };
+    }
+
+    let angle = me.forward.angle_between(desired);
+    let mut input = 0;
+    if angle > STEER_DEADBAND {
+        input |= INPUT_LEFT;
+    } else if angle < -STEER_DEADBAND {
+        input |= INPUT_RIGHT;
+    }
+    if angle.abs() < DRIVE_ANGLE {
+        input |= INPUT_FORWARD;
+    }
+    input
+}
+
+/// A spot behind the closest obstacle that hides the bot from its target
+fn cover_point(position: Vec2, threat: Vec2, obstacles: &[OrientedRect]) -> Option<Vec2> {
+    obstacles
+        .iter()
+        .filter(|obstacle| obstacle.distance_to(position) <= COVER_SEARCH)
+        .min_by(|a, b| a.distance_to(position).total_cmp(&b.distance_to(position)))
+        .map(|obstacle| {
+            let away = (obstacle.center - threat).normalize_or_zero();
+            obstacle.center + away * (obstacle.half_extents.max_element() + COVER_GAP)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEW_RANGE: f32 = 50.;
+
+    fn tank(handle: usize, team: Team, position: Vec2) -> TankView {
+        TankView {
+            handle,
+            team,
+            position,
+            forward: Vec2::Y,
+            velocity: Vec2::ZERO,
+            health: 1.,
+        }
+    }
+
+    fn target_of(current: Option<usize>, tanks: &[TankView], obstacles: &[OrientedRect]) -> Option<usize> {
+        choose_target(&tanks[0], current, VIEW_RANGE, tanks, obstacles).map(|tank| tank.handle)
+    }
+
+    #[test]
+    fn bots_go_for_the_closest_enemy_in_sight() {
+        let tanks = [
+            tank(0, Team(0), Vec2::ZERO),
+            tank(1, Team(1), Vec2::new(30., 0.)),
+            tank(2, Team(1), Vec2::new(0., -20.)),
+            // teammates and enemies out of range are left alone
+            tank(3, Team(0), Vec2::new(5., 0.)),
+            tank(4, Team(1), Vec2::new(0., VIEW_RANGE + 1.)),
+        ];
+        assert_eq!(target_of(None, &tanks, &[]), Some(2));
+    }
+
+    #[test]
+    fn bots_ignore_enemies_they_cant_see() {
+        let mut tanks = [
+            tank(0, Team(0), Vec2::ZERO),
+            tank(1, Team(1), Vec2::new(30., 0.)),
+            tank(2, Team(1), Vec2::new(0., -20.)),
+        ];
+        let wall = OrientedRect::axis_aligned(Vec2::new(0., -10.), Vec2::new(5., 1.));
+        assert_eq!(target_of(None, &tanks, &[wall]), Some(1));
+
+        tanks[1].health = 0.;
+        assert_eq!(target_of(None, &tanks, &[wall]), None);
+    }
+
+    #[test]
+    fn bots_stick_with_their_target_while_they_can_see_it() {
+        let tanks = [
+            tank(0, Team(0), Vec2::ZERO),
+            tank(1, Team(1), Vec2::new(30., 0.)),
+            tank(2, Team(1), Vec2::new(0., -20.)),
+        ];
+        assert_eq!(target_of(Some(1), &tanks, &[]), Some(1));
+
+        let wall = OrientedRect::axis_aligned(Vec2::new(15., 0.), Vec2::new(1., 5.));
+        assert_eq!(target_of(Some(1), &tanks, &[wall]), Some(2));
+    }
+
+    #[test]
+    fn bots_drive_straight_at_a_destination_ahead() {
+        let me = tank(0, Team(0), Vec2::ZERO);
+        assert_eq!(steer(&me, Vec2::new(0., 20.), &[]), INPUT_FORWARD);
+        assert_eq!(steer(&me, Vec2::new(0., ARRIVE_DISTANCE / 2.), &[]), 0, "already there");
+    }
+
+    #[test]
+    fn bots_turn_towards_their_destination() {
+        let me = tank(0, Team(0), Vec2::ZERO);
+        // a little to the left, turning while driving on
+        assert_eq!(steer(&me, Vec2::new(-5., 20.), &[]), INPUT_LEFT | INPUT_FORWARD);
+        // behind to the right, turning on the spot
+        assert_eq!(steer(&me, Vec2::new(5., -20.), &[]), INPUT_RIGHT);
+    }
+
+    #[test]
+    fn bots_swerve_around_obstacles() {
+        let me = tank(0, Team(0), Vec2::ZERO);
+        // obstacles come in already grown by half a hull
+        let rock = OrientedRect::axis_aligned(Vec2::new(0., 5.), Vec2::splat(1.5));
+        assert_eq!(steer(&me, Vec2::new(0., 20.), &[rock]), INPUT_LEFT | INPUT_FORWARD);
+
+        // with no way past it the bot turns on the spot
+        let wall = OrientedRect::axis_aligned(Vec2::new(0., 4.), Vec2::new(11., 1.5));
+        assert_eq!(steer(&me, Vec2::new(0., 20.), &[wall]), INPUT_LEFT);
+    }
+
+    #[test]
+    fn bots_use_abilities_when_they_help() {
+        let def = Difficulty::Normal.def();
+        let close = Some(def.preferred_range);
+        let far = Some(def.preferred_range + RANGE_SLACK + 1.);
+
+        assert!(def.wants_ability(AbilityKind::Smoke, true, close));
+        assert!(!def.wants_ability(AbilityKind::Smoke, false, close));
+        assert!(def.wants_ability(AbilityKind::Boost, false, far));
+        assert!(!def.wants_ability(AbilityKind::Boost, false, close));
+        assert!(def.wants_ability(AbilityKind::Shield, true, close));
+        assert!(!def.wants_ability(AbilityKind::Shield, true, far));
+        assert!(def.wants_ability(AbilityKind::Repair, true, None));
+        assert!(!def.wants_ability(AbilityKind::Repair, true, close));
+    }
+
+    #[test]
+    fn bots_take_cover_on_the_far_side_of_an_obstacle() {
+        let rock = OrientedRect::axis_aligned(Vec2::new(10., 0.), Vec2::splat(2.));
+        let cover = cover_point(Vec2::ZERO, Vec2::new(-30., 0.), &[rock]).unwrap();
+        assert!(cover.x > rock.center.x + rock.half_extents.x);
+        assert!(!line_of_sight(Vec2::new(-30., 0.), cover, &[rock], &[]));
+
+        assert!(cover_point(Vec2::ZERO, Vec2::new(-30., 0.), &[]).is_none());
+    }
+}
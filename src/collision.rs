@@ -0,0 +1,236 @@
+use bevy::prelude::*;
+
+/// A rectangle that can be rotated, used for hulls and obstacles
+#[derive(Clone, Copy, Debug)]
+pub struct OrientedRect {
+    pub center: Vec2,
+    pub half_extents: Vec2,
+    /// unit vector along the rectangle's local X axis
+    pub x_axis: Vec2,
+}
+
+impl OrientedRect {
+    pub fn axis_aligned(center: Vec2, half_extents: Vec2) -> Self {
+        Self {
+            center,
+            half_extents,
+            x_axis: Vec2::X,
+        }
+    }
+
+    /// The rectangle of the given size centred on and rotated with a transform
+    pub fn from_transform(transform: &Transform, size: Vec2) -> Self {
+        Self {
+            center: transform.translation.xy(),
+            half_extents: size / 2.,
+            x_axis: (transform.rotation * Vec3::X).xy(),
+        }
+    }
+
+    pub fn y_axis(&self) -> Vec2 {
+        self.x_axis.perp()
+    }
+
+    /// A world position expressed in the rectangle's local frame
+    pub fn local_point(&self, world: Vec2) -> Vec2 {
+        let offset = world - self.center;
+        Vec2::new(offset.dot(self.x_axis), offset.dot(self.y_axis()))
+    }
+
+    /// Distance from a point to the nearest edge of the rectangle, zero for points inside
+    pub fn distance_to(&self, point: Vec2) -> f32 {
+        (self.local_point(point).abs() - self.half_extents).max(Vec2::ZERO).length()
+    }
+
+    /// Smallest and largest extent of the rectangle projected onto an axis
+    fn project(&self, axis: Vec2) -> (f32, f32) {
+        let center = self.center.dot(axis);
+        let radius = self.half_extents.x * self.x_axis.dot(axis).abs()
+            + self.half_extents.y * self.y_axis().dot(axis).abs();
+        (center - radius, center + radius)
+    }
+
+    /// How far and in which direction this rectangle has to move to stop overlapping `other`,
+    /// `None` when they don't overlap
+    pub fn separation(&self, other: &OrientedRect) -> Option<Vec2> {
+        let mut smallest: Option<Vec2> = None;
+
+        // separating axis theorem, for two rectangles only their four edge normals need testing
+        for axis in [self.x_axis, self.y_axis(), other.x_axis, other.y_axis()] {
+            let (min_a, max_a) = self.project(axis);
+            let (min_b, max_b) = other.project(axis);
+            let overlap = max_a.min(max_b) - min_a.max(min_b);
+            if overlap <= 0. {
+                return None;
+            }
+
+            if smallest.is_none_or(|push| overlap < push.length()) {
+                // push away from the other rectangle's centre
+                let direction = if (self.center - other.center).dot(axis) < 0. { -axis } else { axis };
+                smallest = Some(direction * overlap);
+            }
+        }
+
+        smallest
+    }
+
+    /// Where the segment from `start` to `end` first enters the rectangle, as the fraction of the
+    /// way along the segment and the world space normal of the face that was hit
+    pub fn segment_hit(&self, start: Vec2, end: Vec2) -> Option<(f32, Vec2)> {
+        let local_start = self.local_point(start);
+        let local_delta = self.local_point(end) - local_start;
+
+        let mut t_enter = 0.0f32;
+        let mut t_exit = 1.0f32;
+        let mut normal = Vec2::ZERO;
+
+        // slab test against each pair of faces in the rectangle's local frame
+        for (origin, delta, half, face) in [
+            (local_start.x, local_delta.x, self.half_extents.x, Vec2::X),
+            (local_start.y, local_delta.y, self.half_extents.y, Vec2::Y),
+        ] {
+            if delta.abs() < f32::EPSILON {
+                if origin.abs() > half {
+                    return None;
+                }
+                continue;
+            }
+
+            let t_near = (-half - origin) / delta;
+            let t_far = (half - origin) / delta;
+            let (t_near, t_far, face) = if t_near < t_far {
+                (t_near, t_far, -face)
+            } else {
+                (t_far, t_near, face)
+            };
+
+            if t_near > t_enter {
+                t_enter = t_near;
+                normal = face;
+            }
+            t_exit = t_exit.min(t_far);
+            if t_enter > t_exit {
+                return None;
+            }
+        }
+
+        // starting inside the rectangle counts as an immediate hit against the direction of travel
+        if normal == Vec2::ZERO {
+            normal = -local_delta.normalize_or_zero();
+        }
+
+        Some((t_enter, self.x_axis * normal.x + self.y_axis() * normal.y))
+    }
+}
+
+/// Distance from a point to the closest point on the segment between `start` and `end`
+pub fn segment_distance(start: Vec2, end: Vec2, point: Vec2) -> f32 {
+    let delta = end - start;
+    let t = if delta.length_squared() < f32::EPSILON {
+        0.
+    } else {
+        ((point - start).dot(delta) / delta.length_squared()).clamp(0., 1.)
+    };
+    point.distance(start + delta * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A square two meters across
+    fn square(center: Vec2) -> OrientedRect {
+        OrientedRect::axis_aligned(center, Vec2::ONE)
+    }
+
+    /// A square two meters across turned by 45 degrees
+    fn diamond(center: Vec2) -> OrientedRect {
+        OrientedRect {
+            center,
+            half_extents: Vec2::ONE,
+            x_axis: Vec2::new(1., 1.).normalize(),
+        }
+    }
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(actual.abs_diff_eq(expected, 1e-4), "expected {expected}, got {actual}");
+    }
+
+    #[test]
+    fn overlapping_rects_are_pushed_apart_the_short_way() {
+        let push = square(Vec2::ZERO).separation(&square(Vec2::new(1.5, 0.2))).expect("rects overlap");
+        assert_near(push, Vec2::new(-0.5, 0.));
+        // the other one gets pushed the opposite way
+        let push = square(Vec2::new(1.5, 0.2)).separation(&square(Vec2::ZERO)).expect("rects overlap");
+        assert_near(push, Vec2::new(0.5, 0.));
+    }
+
+    #[test]
+    fn touching_rects_dont_overlap() {
+        assert!(square(Vec2::ZERO).separation(&square(Vec2::new(2., 0.))).is_none());
+        assert!(square(Vec2::ZERO).separation(&square(Vec2::new(2., 2.))).is_none());
+    }
+
+    #[test]
+    fn distant_rects_dont_overlap() {
+        assert!(square(Vec2::ZERO).separation(&square(Vec2::new(3., 0.5))).is_none());
+    }
+
+    #[test]
+    fn rotated_rects_overlap_by_their_corners() {
+        // the diamond's corner reaches sqrt(2) along X, past the square's near edge at 1.3
+        let push = diamond(Vec2::ZERO).separation(&square(Vec2::new(2.3, 0.))).expect("rects overlap");
+        assert_near(push, Vec2::new(2.3 - 1. - std::f32::consts::SQRT_2, 0.));
+    }
+
+    #[test]
+    fn rotated_rects_apart_inside_their_bounding_boxes() {
+        // the bounding boxes overlap, but the square sits off the diamond's flat side
+        let small = OrientedRect::axis_aligned(Vec2::splat(1.3), Vec2::splat(0.5));
+        assert!(diamond(Vec2::ZERO).separation(&small).is_none());
+        assert!(small.separation(&diamond(Vec2::ZERO)).is_none());
+    }
+
+    #[test]
+    fn segment_hits_the_near_face() {
+        let (t, normal) = square(Vec2::ZERO).segment_hit(Vec2::new(-3., 0.5), Vec2::new(3., 0.5)).expect("hit");
+        assert!((t - 1. / 3.).abs() < 1e-5);
+        assert_near(normal, -Vec2::X);
+
+        let (t, normal) = square(Vec2::ZERO).segment_hit(Vec2::new(0.5, 4.), Vec2::new(0.5, 0.)).expect("hit");
+        assert!((t - 0.75).abs() < 1e-5);
+        assert_near(normal, Vec2::Y);
+    }
+
+    #[test]
+    fn axis_parallel_segment_beside_the_rect_misses() {
+        assert!(square(Vec2::ZERO).segment_hit(Vec2::new(-3., 1.5), Vec2::new(3., 1.5)).is_none());
+        assert!(square(Vec2::ZERO).segment_hit(Vec2::new(-1.5, -3.), Vec2::new(-1.5, 3.)).is_none());
+    }
+
+    #[test]
+    fn segment_stopping_short_misses() {
+        assert!(square(Vec2::ZERO).segment_hit(Vec2::new(-3., 0.), Vec2::new(-1.5, 0.)).is_none());
+    }
+
+    #[test]
+    fn segment_starting_inside_hits_straight_away() {
+        let (t, normal) = square(Vec2::ZERO).segment_hit(Vec2::new(0.2, 0.), Vec2::new(3., 0.)).expect("hit");
+        assert_eq!(t, 0.);
+        assert_near(normal, -Vec2::X);
+    }
+
+    #[test]
+    fn segment_hits_a_rotated_rect_in_world_space() {
+        // a long thin wall turned to run along Y
+        let wall = OrientedRect {
+            center: Vec2::ZERO,
+            half_extents: Vec2::new(2., 0.5),
+            x_axis: Vec2::Y,
+        };
+        let (t, normal) = wall.segment_hit(Vec2::new(-3., 1.), Vec2::new(3., 1.)).expect("hit");
+        assert!((t - 2.5 / 6.).abs() < 1e-5);
+        assert_near(normal, -Vec2::X);
+        assert!(wall.segment_hit(Vec2::new(-3., 2.5), Vec2::new(3., 2.5)).is_none());
+    }
+}
@@ -0,0 +1,422 @@
+use bevy::prelude::*;
+
+use crate::map::{MapData, CUSTOM_MAP_PATH};
+use crate::objective::{draw_objective, ObjectiveDef, DEFAULT_OBJECTIVE_RADIUS};
+use crate::obstacle::{Obstacle, ObstacleDef, ObstacleKind};
+use crate::pickup::{Pickup, PickupDef, PickupKind, DEFAULT_RESPAWN_TIME, PICKUP_KINDS};
+use crate::spawn::SpawnDef;
+use crate::terrain::{TerrainGrid, TerrainTile, TerrainType, TILES_PER_SIDE, TILE_SIZE};
+use crate::{AppState, MainCamera, MyScale, MyWorldCoords, MAP_SIZE};
+
+/// Terrain the number keys paint, in key order
+const TERRAIN_KEYS: [(KeyCode, TerrainType); 6] = [
+    (KeyCode::Key1, TerrainType::Grass),
+    (KeyCode::Key2, TerrainType::Road),
+    (KeyCode::Key3, TerrainType::Mud),
+    (KeyCode::Key4, TerrainType::Sand),
+    (KeyCode::Key5, TerrainType::Water),
+    (KeyCode::Key6, TerrainType::Ice),
+];
+/// Largest terrain brush in tiles
+const MAX_BRUSH: usize = 8;
+/// How close the cursor has to be to a spawn point, pickup or objective's centre to erase it in meters
+const PICK_RADIUS: f32 = 2.;
+/// Obstacles are drawn out on a grid this fine in meters
+const SNAP: f32 = 0.5;
+/// Smallest obstacle that can be drawn out in meters
+const MIN_OBSTACLE_SIZE: f32 = 1.;
+/// Hit points per square meter of a destructible obstacle
+const HEALTH_PER_AREA: f32 = 10.;
+/// Teams spawn points can be kept for, pressing V again moves on to the next
+const SPAWN_TEAMS: [Option<usize>; 3] = [None, Some(0), Some(1)];
+/// How fast the camera pans in screen pixels per second
+const PAN_SPEED: f32 = 600.;
+
+/// What the left mouse button does in the editor
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EditorTool {
+    /// paints tiles while held
+    Terrain(TerrainType),
+    /// drags out a rectangle
+    Obstacle(ObstacleKind),
+    /// places a spawn point, kept for a team or shared by everyone
+    Spawn(Option<usize>),
+    Pickup(PickupKind),
+    /// marks an area worth fighting over
+    Objective,
+}
+
+/// The editor's current tool and settings, the map being edited is the `MapData` resource itself
+#[derive(Resource)]
+pub struct MapEditor {
+    pub tool: EditorTool,
+    /// width and height of the terrain brush in tiles
+    pub brush: usize,
+    /// whether newly placed obstacles can be destroyed
+    pub destructible: bool,
+    /// where the obstacle being dragged out started
+    drag_start: Option<Vec2>,
+    /// result of the last save or load, shown in the HUD
+    message: String,
+}
+
+impl Default for MapEditor {
+    fn default() -> Self {
+        Self {
+            tool: EditorTool::Terrain(TerrainType::Road),
+            brush: 2,
+            destructible: false,
+            drag_start: None,
+            message: String::new(),
+        }
+    }
+}
+
+/// Opens the map saved from the editor last time, or the arena if there isn't one
+pub fn open_editor(mut commands: Commands) {
+    let map = MapData::load(CUSTOM_MAP_PATH).unwrap_or_else(|err| {
+        info!("starting the editor from the arena: {err}");
+        MapData::arena()
+    });
+    commands.insert_resource(map);
+    commands.init_resource::<MapEditor>();
+}
+
+/// Clears away the sprites built from the map so they can be built again from the edited map
+#[allow(clippy::type_complexity)]
+pub fn clear_map_sprites(
+    mut commands: Commands,
+    sprite_query: Query<Entity, Or<(With<TerrainTile>, With<Obstacle>, With<Pickup>)>>,
+) {
+    for entity in &sprite_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Picks tools and settings from the keyboard, saves with Ctrl+S, reloads with Ctrl+O and goes back to the menu with
+/// Backspace
+pub fn editor_keys(
+    keys: Res<Input<KeyCode>>,
+    mut editor: ResMut<MapEditor>,
+    mut map: ResMut<MapData>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (key, terrain) in TERRAIN_KEYS {
+        if keys.just_pressed(key) {
+            editor.tool = EditorTool::Terrain(terrain);
+        }
+    }
+    for (key, kind) in [(KeyCode::Z, ObstacleKind::Wall), (KeyCode::X, ObstacleKind::Rock), (KeyCode::C, ObstacleKind::Building)] {
+        if keys.just_pressed(key) {
+            editor.tool = EditorTool::Obstacle(kind);
+        }
+    }
+    // V picks the spawn point tool, pressing it again moves on to the next team
+    if keys.just_pressed(KeyCode::V) {
+        editor.tool = match editor.tool {
+            EditorTool::Spawn(team) => {
+                let index = SPAWN_TEAMS.iter().position(|&other| other == team).unwrap_or_default();
+                EditorTool::Spawn(SPAWN_TEAMS[(index + 1) % SPAWN_TEAMS.len()])
+            }
+            _ => EditorTool::Spawn(SPAWN_TEAMS[0]),
+        };
+    }
+    // P picks the pickup tool, pressing it again moves on to the next kind of pickup
+    if keys.just_pressed(KeyCode::P) {
+        editor.tool = match editor.tool {
+            EditorTool::Pickup(kind) => {
+                let index = PICKUP_KINDS.iter().position(|&other| other == kind).unwrap_or_default();
+                EditorTool::Pickup(PICKUP_KINDS[(index + 1) % PICKUP_KINDS.len()])
+            }
+            _ => EditorTool::Pickup(PICKUP_KINDS[0]),
+        };
+    }
+    if keys.just_pressed(KeyCode::G) {
+        editor.tool = EditorTool::Objective;
+    }
+    if keys.just_pressed(KeyCode::H) {
+        editor.destructible = !editor.destructible;
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        editor.brush = (editor.brush - 1).max(1);
+    }
+    if keys.just_pressed(KeyCode::BracketRight) {
+        editor.brush = (editor.brush + 1).min(MAX_BRUSH);
+    }
+
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if ctrl && keys.just_pressed(KeyCode::S) {
+        editor.message = match std::fs::write(CUSTOM_MAP_PATH, map.to_source()) {
+            Ok(()) => format!("saved to {CUSTOM_MAP_PATH}"),
+            Err(err) => format!("failed to save {CUSTOM_MAP_PATH}: {err}"),
+        };
+        info!("{}", editor.message);
+    }
+    if ctrl && keys.just_pressed(KeyCode::O) {
+        editor.message = match MapData::load(CUSTOM_MAP_PATH) {
+            Ok(loaded) => {
+                *map = loaded;
+                format!("loaded {CUSTOM_MAP_PATH}")
+            }
+            Err(err) => format!("failed to load {err}"),
+        };
+        info!("{}", editor.message);
+    }
+
+    if keys.just_pressed(KeyCode::Back) {
+        next_state.set(AppState::Menu);
+    }
+}
+
+/// Edits the map with the mouse: the left button uses the current tool, the right button erases
+pub fn editor_mouse(
+    buttons: Res<Input<MouseButton>>,
+    cursor: Res<MyWorldCoords>,
+    mut editor: ResMut<MapEditor>,
+    mut map: ResMut<MapData>,
+) {
+    let cursor = cursor.0;
+
+    match editor.tool {
+        EditorTool::Terrain(terrain) => {
+            // the right button paints plain grass, which clears any other terrain
+            let paint = if buttons.pressed(MouseButton::Left) {
+                terrain
+            } else if buttons.pressed(MouseButton::Right) {
+                TerrainType::default()
+            } else {
+                return;
+            };
+            let Some((x, y)) = brush_corner(cursor, editor.brush) else {
+                return;
+            };
+            // only touch the map when a tile actually changes, so the sprites aren't rebuilt every frame
+            let (end_x, end_y) = ((x + editor.brush).min(TILES_PER_SIDE), (y + editor.brush).min(TILES_PER_SIDE));
+            let changed = (y..end_y).any(|ty| (x..end_x).any(|tx| map.terrain.get(tx, ty) != paint));
+            if changed {
+                map.terrain.fill(x, y, editor.brush, editor.brush, paint);
+            }
+            return;
+        }
+        EditorTool::Obstacle(kind) => {
+            if buttons.just_pressed(MouseButton::Left) {
+                editor.drag_start = Some(snap(cursor));
+            }
+            if buttons.just_released(MouseButton::Left) {
+                if let Some(start) = editor.drag_start.take() {
+                    let end = snap(cursor);
+                    let size = (end - start).abs().max(Vec2::splat(MIN_OBSTACLE_SIZE));
+                    let health = editor.destructible.then_some(size.x * size.y * HEALTH_PER_AREA);
+                    map.obstacles.push(ObstacleDef {
+                        kind,
+                        center: (start + end) / 2.,
+                        size,
+                        health,
+                    });
+                }
+            }
+        }
+        EditorTool::Spawn(team) => {
+            if buttons.just_pressed(MouseButton::Left) {
+                map.spawns.push(SpawnDef { center: cursor, team });
+            }
+        }
+        EditorTool::Pickup(kind) => {
+            if buttons.just_pressed(MouseButton::Left) {
+                map.pickups.push(PickupDef {
+                    kind,
+                    center: cursor,
+                    respawn_time: DEFAULT_RESPAWN_TIME,
+                });
+            }
+        }
+        EditorTool::Objective => {
+            if buttons.just_pressed(MouseButton::Left) {
+                map.objectives.push(ObjectiveDef {
+                    center: cursor,
+                    radius: DEFAULT_OBJECTIVE_RADIUS,
+                });
+            }
+        }
+    }
+
+    // erases the closest spawn point, pickup or objective under the cursor, otherwise the last obstacle placed under it
+    if buttons.just_pressed(MouseButton::Right) {
+        if let Some(index) = map.spawns.iter().position(|spawn| spawn.center.distance(cursor) <= PICK_RADIUS) {
+            map.spawns.remove(index);
+        } else if let Some(index) = map.pickups.iter().position(|pickup| pickup.center.distance(cursor) <= PICK_RADIUS) {
+            map.pickups.remove(index);
+        } else if let Some(index) =
+            map.objectives.iter().position(|objective| objective.center.distance(cursor) <= PICK_RADIUS)
+        {
+            map.objectives.remove(index);
+        } else if let Some(index) = map
+            .obstacles
+            .iter()
+            .rposition(|obstacle| ((cursor - obstacle.center).abs() - obstacle.size / 2.).max_element() <= 0.)
+        {
+            map.obstacles.remove(index);
+        }
+    }
+}
+
+/// Bottom left tile of the terrain brush centred on the cursor, `None` while the cursor is off the map
+fn brush_corner(cursor: Vec2, brush: usize) -> Option<(usize, usize)> {
+    let tile = ((cursor + MAP_SIZE as f32 / 2.) / TILE_SIZE).floor();
+    let range = 0.0..TILES_PER_SIDE as f32;
+    if !range.contains(&tile.x) || !range.contains(&tile.y) {
+        return None;
+    }
+    let half = (brush - 1) / 2;
+    Some(((tile.x as usize).saturating_sub(half), (tile.y as usize).saturating_sub(half)))
+}
+
+fn snap(point: Vec2) -> Vec2 {
+    (point / SNAP).round() * SNAP
+}
+
+/// Pans the camera with WASD or the arrow keys, at the same speed on screen whatever the zoom
+pub fn pan_camera(
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    scale: Res<MyScale>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    let mut direction = Vec2::ZERO;
+    if keys.any_pressed([KeyCode::Up, KeyCode::W]) {
+        direction.y += 1.;
+    }
+    if keys.any_pressed([KeyCode::Down, KeyCode::S]) {
+        direction.y -= 1.;
+    }
+    if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
+        direction.x -= 1.;
+    }
+    if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
+        direction.x += 1.;
+    }
+    // S saves while Ctrl is held
+    if direction == Vec2::ZERO || keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    for mut transform in &mut camera_query {
+        let step = direction.normalize() * PAN_SPEED / scale.0.max(1.) * time.delta_seconds();
+        transform.translation += Vec3::from((step, 0.));
+    }
+}
+
+/// Marks spawn points and objectives, outlines the brush and shows the obstacle being dragged out
+pub fn draw_editor(
+    editor: Res<MapEditor>,
+    map: Res<MapData>,
+    cursor: Res<MyWorldCoords>,
+    mut gizmos: Gizmos,
+) {
+    for spawn in &map.spawns {
+        gizmos.circle_2d(spawn.center, 2., spawn_color(spawn.team));
+    }
+    for objective in &map.objectives {
+        draw_objective(&mut gizmos, objective.center, objective.radius);
+    }
+
+    match editor.tool {
+        EditorTool::Terrain(terrain) => {
+            if let Some((x, y)) = brush_corner(cursor.0, editor.brush) {
+                let size = Vec2::splat(editor.brush as f32 * TILE_SIZE);
+                let center = TerrainGrid::tile_center(x, y) - TILE_SIZE / 2. + size / 2.;
+                gizmos.rect_2d(center, 0., size, terrain.color());
+            }
+        }
+        EditorTool::Obstacle(kind) => {
+            let end = snap(cursor.0);
+            let start = editor.drag_start.unwrap_or(end);
+            let size = (end - start).abs().max(Vec2::splat(MIN_OBSTACLE_SIZE));
+            gizmos.rect_2d((start + end) / 2., 0., size, kind.color());
+        }
+        EditorTool::Spawn(team) => {
+            gizmos.circle_2d(cursor.0, 2., spawn_color(team));
+        }
+        EditorTool::Pickup(kind) => {
+            gizmos.circle_2d(cursor.0, 1., kind.color());
+        }
+        EditorTool::Objective => {
+            draw_objective(&mut gizmos, cursor.0, DEFAULT_OBJECTIVE_RADIUS);
+        }
+    }
+}
+
+fn spawn_color(team: Option<usize>) -> Color {
+    match team {
+        None => Color::CYAN,
+        Some(0) => Color::BLUE,
+        Some(_) => Color::RED,
+    }
+}
+
+/// Text listing the editor's tools and what is selected
+#[derive(Component)]
+pub struct EditorHud;
+
+pub fn spawn_editor_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Px(10.),
+            ..default()
+        }),
+        EditorHud,
+    ));
+}
+
+pub fn update_editor_hud(
+    editor: Res<MapEditor>,
+    mut hud_query: Query<&mut Text, With<EditorHud>>,
+) {
+    let tool = match editor.tool {
+        EditorTool::Terrain(terrain) => format!("paint {} ({}x{} brush)", terrain.name(), editor.brush, editor.brush),
+        EditorTool::Obstacle(kind) => format!("{}{}", kind.name(), if editor.destructible { " (destructible)" } else { "" }),
+        EditorTool::Spawn(None) => "shared spawn point".to_string(),
+        EditorTool::Spawn(Some(team)) => format!("spawn point for team {team}"),
+        EditorTool::Pickup(kind) => format!("{} pickup", kind.name()),
+        EditorTool::Objective => "objective".to_string(),
+    };
+    let value = format!(
+        "Map editor: {tool}\n\
+         1-6 terrain, [ ] brush size\n\
+         Z wall, X rock, C building, H destructible\n\
+         V spawn point and team, P pickup, G objective\n\
+         Left click place, right click erase\n\
+         WASD pan, Ctrl+S save, Ctrl+O load, Backspace menu\n\
+         {}",
+        editor.message,
+    );
+
+    for mut text in &mut hud_query {
+        text.sections[0].value.clone_from(&value);
+    }
+}
+
+/// Clears the editor away when going back to the menu
+pub fn close_editor(
+    mut commands: Commands,
+    hud_query: Query<Entity, With<EditorHud>>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    for entity in &hud_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    for mut transform in &mut camera_query {
+        transform.translation = Vec3::new(0., 0., transform.translation.z);
+    }
+    commands.remove_resource::<MapEditor>();
+}
@@ -0,0 +1,314 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_ggrs::*;
+
+use crate::collision::{segment_distance, OrientedRect};
+use crate::health::Health;
+use crate::obstacle::{is_standing, Destructible, Obstacle};
+use crate::projectile::Projectile;
+use crate::smoke::SmokeCloud;
+use crate::weapon::WeaponKind;
+use crate::{Nameplate, Player, Target, Team, Turret, MAP_SIZE};
+
+/// Width and height of a fog of war cell in meters
+const FOG_CELL_SIZE: f32 = 10.;
+/// Number of fog cells along each side of the map
+const FOG_CELLS_PER_SIDE: usize = (MAP_SIZE as f32 / FOG_CELL_SIZE) as usize;
+/// How dark the map gets outside of vision
+const FOG_ALPHA: f32 = 0.45;
+/// How close a tank on the local team has to get to an enemy mine to spot it in meters
+const MINE_SPOT_RANGE: f32 = 8.;
+/// How faded a mine is drawn while it is still arming
+const ARMING_ALPHA: f32 = 0.4;
+
+/// What the local client can currently see, purely cosmetic so it is never rolled back
+#[derive(Resource, Default)]
+pub struct FogOfWar {
+    /// handles of every tank the local team can see, including its own
+    pub visible_handles: HashSet<usize>,
+    /// handles of every tank on the local team
+    pub friendly_handles: HashSet<usize>,
+    /// handles of every tank with a smoke screen out, enemies can't see their reticle or nameplate
+    pub smoked_handles: HashSet<usize>,
+    /// whether each fog cell is in vision, indexed by `y * FOG_CELLS_PER_SIDE + x`
+    lit_cells: Vec<bool>,
+}
+
+impl FogOfWar {
+    /// Whether a point on the map is in vision of the local team, points off the fog grid always are
+    pub fn is_lit(&self, point: Vec2) -> bool {
+        let cell = ((point + MAP_SIZE as f32 / 2.) / FOG_CELL_SIZE).floor();
+        if cell.min_element() < 0. || cell.max_element() >= FOG_CELLS_PER_SIDE as f32 {
+            return true;
+        }
+        let index = cell.y as usize * FOG_CELLS_PER_SIDE + cell.x as usize;
+        self.lit_cells.get(index).copied().unwrap_or(true)
+    }
+}
+
+/// A square of the fog overlay
+#[derive(Component)]
+pub struct FogCell(usize);
+
+/// Whether nothing blocks the straight line between two points, `clouds` are the centre and radius of every smoke cloud
+pub fn line_of_sight(from: Vec2, to: Vec2, occluders: &[OrientedRect], clouds: &[(Vec2, f32)]) -> bool {
+    occluders
+        .iter()
+        .all(|occluder| occluder.segment_hit(from, to).is_none())
+        && clouds
+            .iter()
+            .all(|&(center, radius)| segment_distance(from, to, center) > radius)
+}
+
+/// Collects the rectangles of every obstacle that is still standing
+pub fn standing_obstacles<'a>(
+    obstacle_query: impl IntoIterator<Item = (&'a Obstacle, &'a Transform, Option<&'a Destructible>)>,
+) -> Vec<OrientedRect> {
+    obstacle_query
+        .into_iter()
+        .filter(|(_, _, destructible)| is_standing(*destructible))
+        .map(|(obstacle, transform, _)| obstacle.rect(transform))
+        .collect()
+}
+
+/// Spawns the overlay that dims the parts of the map outside of vision
+pub fn spawn_fog(mut commands: Commands) {
+    for y in 0..FOG_CELLS_PER_SIDE {
+        for x in 0..FOG_CELLS_PER_SIDE {
+            let center = (Vec2::new(x as f32, y as f32) + 0.5) * FOG_CELL_SIZE - MAP_SIZE as f32 / 2.;
+            commands.spawn((
+                SpriteBundle {
+                    transform: Transform::from_translation(Vec3::from((center, 90.))),
+                    sprite: Sprite {
+                        color: Color::BLACK.with_a(0.),
+                        custom_size: Some(Vec2::splat(FOG_CELL_SIZE)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                FogCell(y * FOG_CELLS_PER_SIDE + x),
+            ));
+        }
+    }
+}
+
+/// Works out which tanks and which parts of the map the local team can see
+///
+/// Tanks that are down can't see anything and can't be seen until they are back.
+pub fn update_fog(
+    local_players: Option<Res<LocalPlayers>>,
+    mut fog: ResMut<FogOfWar>,
+    player_query: Query<(&Player, &Team, &Transform, &Health)>,
+    obstacle_query: Query<(&Obstacle, &Transform, Option<&Destructible>)>,
+    smoke_query: Query<(&SmokeCloud, &Transform)>,
+) {
+    fog.smoked_handles = smoke_query.iter().map(|(cloud, _)| cloud.owner).collect();
+
+    let local_handles = local_players.map(|local_players| local_players.0.clone()).unwrap_or_default();
+
+    // until we are in a session there is nobody to hide anything from
+    if local_handles.is_empty() {
+        fog.visible_handles = player_query
+            .iter()
+            .filter(|(_, _, _, health)| health.current > 0.)
+            .map(|(ship, _, _, _)| ship.handle)
+            .collect();
+        fog.friendly_handles = fog.visible_handles.clone();
+        fog.lit_cells = vec![true; FOG_CELLS_PER_SIDE * FOG_CELLS_PER_SIDE];
+        return;
+    }
+
+    let local_teams: HashSet<Team> = player_query
+        .iter()
+        .filter(|(ship, _, _, _)| local_handles.contains(&ship.handle))
+        .map(|(_, team, _, _)| *team)
+        .collect();
+
+    fog.friendly_handles = player_query
+        .iter()
+        .filter(|(_, team, _, _)| local_teams.contains(*team))
+        .map(|(ship, _, _, _)| ship.handle)
+        .collect();
+
+    // vision is shared with everyone on the local team
+    let observers: Vec<(Vec2, f32)> = player_query
+        .iter()
+        .filter(|(_, team, _, health)| local_teams.contains(*team) && health.current > 0.)
+        .map(|(ship, _, transform, _)| (transform.translation.xy(), ship.view_range))
+        .collect();
+
+    let occluders = standing_obstacles(&obstacle_query);
+    let clouds: Vec<(Vec2, f32)> = smoke_query
+        .iter()
+        .map(|(cloud, transform)| (transform.translation.xy(), cloud.radius()))
+        .collect();
+    let in_vision = |point: Vec2| {
+        observers.iter().any(|&(eye, range)| {
+            eye.distance(point) <= range && line_of_sight(eye, point, &occluders, &clouds)
+        })
+    };
+
+    fog.visible_handles = player_query
+        .iter()
+        .filter(|(_, _, _, health)| health.current > 0.)
+        .filter(|(_, team, transform, _)| local_teams.contains(*team) || in_vision(transform.translation.xy()))
+        .map(|(ship, _, _, _)| ship.handle)
+        .collect();
+
+    // only cells within view range of an observer can be lit
+    let cell_of = |pos: f32| {
+        ((pos + MAP_SIZE as f32 / 2.) / FOG_CELL_SIZE).clamp(0., (FOG_CELLS_PER_SIDE - 1) as f32) as usize
+    };
+    let mut lit_cells = vec![false; FOG_CELLS_PER_SIDE * FOG_CELLS_PER_SIDE];
+    for &(eye, range) in &observers {
+        for y in cell_of(eye.y - range)..=cell_of(eye.y + range) {
+            for x in cell_of(eye.x - range)..=cell_of(eye.x + range) {
+                let index = y * FOG_CELLS_PER_SIDE + x;
+                if lit_cells[index] {
+                    continue;
+                }
+                let center = (Vec2::new(x as f32, y as f32) + 0.5) * FOG_CELL_SIZE - MAP_SIZE as f32 / 2.;
+                lit_cells[index] = in_vision(center);
+            }
+        }
+    }
+    fog.lit_cells = lit_cells;
+}
+
+/// Dims the fog cells outside of vision
+pub fn draw_fog(
+    fog: Res<FogOfWar>,
+    mut cell_query: Query<(&FogCell, &mut Sprite)>,
+) {
+    if !fog.is_changed() {
+        return;
+    }
+
+    for (cell, mut sprite) in &mut cell_query {
+        let lit = fog.lit_cells.get(cell.0).copied().unwrap_or(true);
+        let alpha = if lit { 0. } else { FOG_ALPHA };
+        if sprite.color.a() != alpha {
+            sprite.color.set_a(alpha);
+        }
+    }
+}
+
+/// Hides tanks the local team can't see, they are still simulated for rollback as normal
+///
+/// Enemy reticles and nameplates are also hidden while their tank has a smoke screen out.
+#[allow(clippy::type_complexity)]
+pub fn hide_unseen_tanks(
+    fog: Res<FogOfWar>,
+    mut part_query: Query<(AnyOf<(&Player, &Turret, &Target, &Nameplate)>, &mut Visibility)>,
+) {
+    for ((ship, turret, target, nameplate), mut visibility) in &mut part_query {
+        let Some(handle) = ship
+            .map(|ship| ship.handle)
+            .or(turret.map(|turret| turret.handle))
+            .or(target.map(|target| target.handle))
+            .or(nameplate.map(|nameplate| nameplate.handle))
+        else {
+            continue;
+        };
+
+        let smoked = (target.is_some() || nameplate.is_some())
+            && fog.smoked_handles.contains(&handle)
+            && !fog.friendly_handles.contains(&handle);
+        visibility.set_if_neq(if fog.visible_handles.contains(&handle) && !smoked {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+/// Hides enemy shells outside of vision so they don't give away where they were fired from
+///
+/// Enemy mines stay hidden until a tank on the local team drives close enough to spot them, and mines that are still
+/// arming are faded.
+pub fn hide_enemy_projectiles(
+    fog: Res<FogOfWar>,
+    player_query: Query<(&Player, &Transform)>,
+    mut mine_query: Query<(&Projectile, &Transform, &mut Sprite, &mut Visibility), Without<Player>>,
+) {
+    let spotters: Vec<Vec2> = player_query
+        .iter()
+        .filter(|(ship, _)| fog.friendly_handles.contains(&ship.handle))
+        .map(|(_, transform)| transform.translation.xy())
+        .collect();
+
+    for (projectile, transform, mut sprite, mut visibility) in &mut mine_query {
+        let position = transform.translation.xy();
+        let friendly = fog.friendly_handles.contains(&projectile.owner);
+        if projectile.weapon != WeaponKind::Mines {
+            visibility.set_if_neq(if friendly || fog.is_lit(position) {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            });
+            continue;
+        }
+
+        let spotted = friendly || spotters.iter().any(|spotter| spotter.distance(position) <= MINE_SPOT_RANGE);
+        visibility.set_if_neq(if spotted {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+
+        let alpha = if projectile.arming > 0. { ARMING_ALPHA } else { 1. };
+        if sprite.color.a() != alpha {
+            sprite.color.set_a(alpha);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A wall ten meters long running along Y halfway between the origin and (20, 0)
+    fn wall() -> OrientedRect {
+        OrientedRect::axis_aligned(Vec2::new(10., 0.), Vec2::new(0.5, 5.))
+    }
+
+    #[test]
+    fn open_ground_is_in_sight() {
+        assert!(line_of_sight(Vec2::ZERO, Vec2::new(20., 0.), &[], &[]));
+    }
+
+    #[test]
+    fn obstacles_block_sight() {
+        assert!(!line_of_sight(Vec2::ZERO, Vec2::new(20., 0.), &[wall()], &[]));
+        assert!(!line_of_sight(Vec2::new(20., 3.), Vec2::new(0., -3.), &[wall()], &[]));
+    }
+
+    #[test]
+    fn sight_passes_around_the_end_of_an_obstacle() {
+        assert!(line_of_sight(Vec2::ZERO, Vec2::new(20., 12.), &[wall()], &[]));
+        assert!(line_of_sight(Vec2::ZERO, Vec2::new(8., 0.), &[wall()], &[]));
+    }
+
+    #[test]
+    fn destroyed_obstacles_dont_block_sight() {
+        let obstacle = Obstacle {
+            half_extents: Vec2::new(0.5, 5.),
+        };
+        let transform = Transform::from_xyz(10., 0., 50.);
+        let intact = Destructible {
+            health: 50.,
+            max_health: 100.,
+        };
+        let destroyed = Destructible {
+            health: 0.,
+            max_health: 100.,
+        };
+
+        let occluders = standing_obstacles([(&obstacle, &transform, None), (&obstacle, &transform, Some(&intact))]);
+        assert_eq!(occluders.len(), 2);
+        assert!(!line_of_sight(Vec2::ZERO, Vec2::new(20., 0.), &occluders, &[]));
+
+        let occluders = standing_obstacles([(&obstacle, &transform, Some(&destroyed))]);
+        assert!(occluders.is_empty());
+        assert!(line_of_sight(Vec2::ZERO, Vec2::new(20., 0.), &occluders, &[]));
+    }
+}
@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+
+use crate::fog::FogOfWar;
+use crate::module::Modules;
+use crate::pickup::PowerUps;
+use crate::spawn::{safest_spawn, SpawnPoints, SPAWN_PROTECTION};
+use crate::status::StatusEffects;
+use crate::weapon::Armament;
+use crate::{Player, Team, Velocity};
+
+/// Hit points of a tank, rolled back so every peer agrees on who is still alive
+#[derive(Component, Clone)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+    /// whether the tank comes straight back once destroyed, game modes that hold tanks back clear this
+    pub respawn: bool,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            respawn: true,
+        }
+    }
+}
+
+/// Puts destroyed tanks back at whichever of their spawn points is furthest from the enemy, with full health, ammo and
+/// modules, no status effects and a short spawn protection shield
+///
+/// Tanks that aren't allowed to respawn yet stay where they were destroyed.
+#[allow(clippy::type_complexity)]
+pub fn respawn_destroyed_tanks(
+    mut player_query: Query<(
+        Entity,
+        &Player,
+        &Team,
+        &mut Health,
+        &mut Transform,
+        &mut Velocity,
+        &mut Armament,
+        &mut PowerUps,
+        &mut StatusEffects,
+        &mut Modules,
+        &SpawnPoints,
+    )>,
+) {
+    let mut living: Vec<(Team, Vec2)> = player_query
+        .iter()
+        .filter(|(_, _, _, health, ..)| health.current > 0.)
+        .map(|(_, _, team, _, transform, ..)| (*team, transform.translation.xy()))
+        .collect();
+
+    // in handle order, so tanks coming back on the same frame take the same spawn points on every peer
+    let mut respawning: Vec<(usize, Entity)> = player_query
+        .iter()
+        .filter(|(_, _, _, health, ..)| health.current <= 0. && health.respawn)
+        .map(|(entity, ship, ..)| (ship.handle, entity))
+        .collect();
+    respawning.sort_by_key(|(handle, _)| *handle);
+
+    for (_, entity) in respawning {
+        let Ok((_, _, team, mut health, mut transform, mut velocity, mut armament, mut power_ups, mut status, mut modules, spawn_points)) =
+            player_query.get_mut(entity)
+        else {
+            continue;
+        };
+
+        let enemies: Vec<Vec2> = living.iter().filter(|(other, _)| other != team).map(|(_, pos)| *pos).collect();
+        let occupied: Vec<Vec2> = living.iter().map(|(_, pos)| *pos).collect();
+        let spawn = safest_spawn(&spawn_points.0, &enemies, &occupied).unwrap_or_default();
+        living.push((*team, spawn));
+
+        health.current = health.max;
+        *transform = Transform::from_translation(Vec3::from((spawn, transform.translation.z)));
+        velocity.0 = Vec2::ZERO;
+        *armament = Armament::default();
+        *power_ups = PowerUps {
+            shield: SPAWN_PROTECTION,
+            ..default()
+        };
+        *status = StatusEffects::default();
+        *modules = Modules::default();
+    }
+}
+
+/// Draws a health bar above every tank the local team can see
+pub fn draw_health_bars(
+    player_query: Query<(&Player, &Health, &Transform)>,
+    fog: Res<FogOfWar>,
+    mut gizmos: Gizmos,
+) {
+    for (ship, health, transform) in &player_query {
+        if !fog.visible_handles.contains(&ship.handle) {
+            continue;
+        }
+
+        let start = transform.translation.xy() + Vec2::new(-1.5, 3.);
+        let fraction = (health.current / health.max).clamp(0., 1.);
+        gizmos.line_2d(start, start + Vec2::new(3., 0.), Color::DARK_GRAY);
+        gizmos.line_2d(start, start + Vec2::new(3. * fraction, 0.), Color::LIME_GREEN);
+    }
+}
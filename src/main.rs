@@ -7,24 +7,93 @@ use bevy::{prelude::*,
     utils::HashMap};
 use bevy_ggrs::*;
 use bevy_matchbox::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+mod ability;
+mod armor;
+mod barricade;
+mod bot;
+mod collision;
+mod editor;
+mod fog;
+mod health;
+mod map;
+mod mapgen;
+mod menu;
+mod minimap;
+mod nav;
+mod module;
+mod objective;
+mod obstacle;
+mod pickup;
+mod projectile;
+mod rng;
+mod smoke;
+mod spawn;
+mod status;
+mod tank_class;
+mod terrain;
+mod training;
+mod waves;
+mod weapon;
+use collision::OrientedRect;
+use map::MapData;
+use obstacle::{is_standing, Destructible, Obstacle};
+use tank_class::TankClass;
+use weapon::{Armament, MySelectedWeapon};
 
 // Constants
+/// Playable area of maps that don't set their own bounds, clipped to the map
 const BOUNDS: Vec2 = Vec2::new(1200.0, 640.0);
 const SCALE_STEP: f32 = 5.;
 const MAX_SCALE: f32 = 100.;
 const MAP_SIZE: u32 = 1000;
 const GRID_WIDTH: f32 = 0.05;
-
-const INPUT_FORWARD: u8 = 1 << 0;
-const INPUT_REVERSE: u8 = 1 << 1;
-const INPUT_LEFT: u8 = 1 << 2;
-const INPUT_RIGHT: u8 = 1 << 3;
-const INPUT_FIRE: u8 = 1 << 4;
+/// Width and length of a tank hull in meters
+const HULL_SIZE: Vec2 = Vec2::new(2.0, 4.0);
+
+const INPUT_FORWARD: u64 = 1 << 0;
+const INPUT_REVERSE: u64 = 1 << 1;
+const INPUT_LEFT: u64 = 1 << 2;
+const INPUT_RIGHT: u64 = 1 << 3;
+const INPUT_FIRE: u64 = 1 << 4;
+const INPUT_LEFT_TRACK_FORWARD: u64 = 1 << 5;
+const INPUT_LEFT_TRACK_REVERSE: u64 = 1 << 6;
+const INPUT_RIGHT_TRACK_FORWARD: u64 = 1 << 7;
+const INPUT_RIGHT_TRACK_REVERSE: u64 = 1 << 8;
+
+/// Bits of the input holding the aim point, one signed 16 bit number per axis
+const INPUT_AIM_X_SHIFT: u64 = 32;
+const INPUT_AIM_Y_SHIFT: u64 = 48;
+/// Size of a step of the encoded aim point in meters
+const AIM_RESOLUTION: f32 = 0.1;
+
+/// How far a stick has to be pushed before it counts as driving a track
+const STICK_DEADZONE: f32 = 0.5;
+
+/// Where a tank's name is drawn relative to its hull, above the health bar
+const NAMEPLATE_OFFSET: Vec3 = Vec3::new(0., 4., 5.);
+/// Nameplate text is laid out in pixels, this brings it down to meters
+const NAMEPLATE_SCALE: f32 = 0.04;
+
+/// The socket channel GGRS runs over
+const GGRS_CHANNEL: usize = 0;
+/// The reliable socket channel the peers tell each other their classes over before the match
+const LOBBY_CHANNEL: usize = 1;
+/// Sent over the lobby channel by the first player to start the match before the room is full, any other byte is a
+/// class
+const LOBBY_START: u8 = u8::MAX;
+/// Seconds a match with bots on waits for the room to fill up before the bots take the handles nobody joined for
+const BOT_FILL_WAIT: f32 = 10.;
+/// The most players the menu lets a match be set up for
+const MAX_PLAYERS: u16 = 4;
 
 //Types
-// The first generic parameter, u8, is the input type: 4-directions + fire fits easily in a single byte
+// The first generic parameter, u64, is the input type: 4-directions + fire + 4 track bits + a 2 bit weapon slot +
+// barricade + 2 ability bits + a ping bit fit in the low two bytes, a minimap ping's position takes the next two
+// bytes and the aim point takes the high four bytes
 // The second parameter is the address type of peers: Matchbox' WebRtcSocket addresses are called `PeerId`s
-type Config = bevy_ggrs::GgrsConfig<u8, PeerId>;
+type Config = bevy_ggrs::GgrsConfig<u64, PeerId>;
 
 // Main
 fn main() {
@@ -47,25 +116,141 @@ fn main() {
             GgrsPlugin::<Config>::default(),
         ))
         .rollback_component_with_clone::<Transform>()
+        .rollback_component_with_clone::<Velocity>()
+        .rollback_component_with_clone::<Turret>()
+        .rollback_component_with_clone::<projectile::Projectile>()
+        .rollback_component_with_clone::<Destructible>()
+        .rollback_component_with_clone::<Armament>()
+        .rollback_component_with_clone::<health::Health>()
+        .rollback_component_with_clone::<pickup::Pickup>()
+        .rollback_component_with_clone::<pickup::PowerUps>()
+        .rollback_component_with_clone::<Obstacle>()
+        .rollback_component_with_clone::<barricade::Barricade>()
+        .rollback_component_with_clone::<barricade::BarricadeLayer>()
+        .rollback_component_with_clone::<ability::Abilities>()
+        .rollback_component_with_clone::<status::StatusEffects>()
+        .rollback_component_with_clone::<module::Modules>()
+        .rollback_component_with_clone::<bot::Bot>()
+        .rollback_component_with_clone::<smoke::SmokeCloud>()
+        .rollback_resource_with_clone::<rng::RollbackRng>()
+        .rollback_resource_with_clone::<waves::WaveState>()
+        .rollback_resource_with_clone::<minimap::Pings>()
+        .rollback_resource_with_clone::<training::DummyDamage>()
+        .init_resource::<rng::RollbackRng>()
+        .init_resource::<waves::WaveState>()
+        .init_resource::<minimap::Pings>()
+        .init_resource::<training::DummyDamage>()
+        .init_resource::<minimap::MinimapInput>()
+        .add_state::<AppState>()
+        .init_resource::<MyGameMode>()
+        .init_resource::<map::MyMapChoice>()
+        .init_resource::<mapgen::MapSeed>()
+        .init_resource::<mapgen::MapGenParams>()
+        .init_resource::<training::TrainingOptions>()
+        .init_resource::<training::DamageMeter>()
         .insert_resource(ClearColor(Color::rgb(0.53, 0.53, 0.53)))
         .init_resource::<MyWorldCoords>()
         .init_resource::<MyScale>()
         .init_resource::<MyNumPlayers>()
+        .init_resource::<MyTankClasses>()
+        .init_resource::<MyTankClass>()
+        .init_resource::<MyBots>()
+        .init_resource::<TankInputs>()
+        .init_resource::<nav::NavGrid>()
+        .init_resource::<nav::NavDebug>()
+        .init_resource::<MyControlScheme>()
+        .init_resource::<fog::FogOfWar>()
+        .init_resource::<MySelectedWeapon>()
         .add_systems(Startup, (
             setup,
-            spawn_players,
-            start_matchbox_socket,))
+            fog::spawn_fog,
+            weapon::spawn_weapon_hud,
+            smoke::setup_smoke,
+            ability::spawn_ability_hud,))
+        .add_systems(OnEnter(AppState::Menu), menu::spawn_menu)
+        .add_systems(OnExit(AppState::Menu), menu::despawn_menu)
+        .add_systems(OnEnter(AppState::Editor), (editor::open_editor, editor::spawn_editor_hud))
+        .add_systems(OnExit(AppState::Editor), (editor::clear_map_sprites, editor::close_editor))
+        .add_systems(Update, (
+            (my_cursor_system, editor::editor_keys, editor::editor_mouse, editor::pan_camera, editor::draw_editor, editor::update_editor_hud).chain(),
+            // the edited map is built the same way as a match's, again whenever it changes
+            (editor::clear_map_sprites, apply_deferred, terrain::spawn_terrain, obstacle::spawn_obstacles, pickup::spawn_pickups)
+                .chain()
+                .run_if(resource_exists_and_changed::<MapData>()),
+        ).run_if(in_state(AppState::Editor)))
+        .add_systems(OnEnter(AppState::Connecting), (
+            start_matchbox_socket.run_if(not(resource_equals(MyGameMode::Training))),
+            training::enter_training_range.run_if(resource_equals(MyGameMode::Training)),))
+        // the world is built before the session starts, so the first rollback snapshot already has every tank in it
+        .add_systems(OnEnter(AppState::InGame), (
+            map::load_map,
+            apply_deferred,
+            (
+                terrain::spawn_terrain,
+                obstacle::spawn_obstacles,
+                pickup::spawn_pickups,
+                spawn_players,
+                minimap::spawn_minimap,
+                training::spawn_training_hud.run_if(resource_equals(MyGameMode::Training)),
+                waves::spawn_wave_hud.run_if(resource_equals(MyGameMode::CoopWaves)),
+            ),
+            apply_deferred,
+            (
+                start_p2p_session.run_if(not(resource_equals(MyGameMode::Training))),
+                training::start_training_session.run_if(resource_equals(MyGameMode::Training)),
+            ),
+        ).chain())
         .add_systems(Update, (
             // my_cursor_system,
             // player_movement_system,
-            draw_client_side,
+            (fog::update_fog, fog::hide_unseen_tanks, fog::hide_enemy_projectiles, fog::draw_fog, draw_client_side, health::draw_health_bars, pickup::draw_shields, status::draw_status_effects, module::draw_modules).chain(),
+            obstacle::draw_destructibles,
+            place_nameplates,
+            pickup::draw_pickups,
+            objective::draw_objectives.run_if(in_state(AppState::InGame)),
+            // the menu and the editor use the number keys too
+            weapon::select_weapon.run_if(in_state(AppState::InGame)),
+            weapon::update_weapon_hud,
+            ability::update_ability_hud,
+            projectile::draw_landing_indicators,
+            nav::toggle_nav_debug,
+            nav::draw_nav_debug,
             zoom_scalingmode,
-            wait_for_players,
+            toggle_control_scheme,
+            (menu::choose_game_mode, menu::toggle_map_choice, menu::toggle_bots, menu::cycle_tank_class, menu::cycle_num_players).run_if(in_state(AppState::Menu)),
+            waves::update_wave_hud.run_if(resource_equals(MyGameMode::CoopWaves)),
+            (training::toggle_training_options, training::measure_damage, training::update_training_hud)
+                .run_if(resource_equals(MyGameMode::Training)),
+            (minimap::update_minimap, minimap::update_minimap_obstacles, minimap::draw_pings)
+                .after(fog::update_fog)
+                .run_if(in_state(AppState::InGame)),
+            wait_for_players
+                .run_if(in_state(AppState::Connecting))
+                .run_if(resource_exists::<MatchboxSocket<MultipleChannels>>()),
             bevy::window::close_on_esc))
         .add_systems(ReadInputs, (
             my_cursor_system,
-            read_local_inputs,))
-        .add_systems(GgrsSchedule, move_players)
+            minimap::click_minimap,
+            read_local_inputs,).chain())
+        .add_systems(GgrsSchedule, (
+            collect_inputs,
+            minimap::update_pings,
+            nav::update_nav_grid,
+            bot::drive_bots,
+            training::drive_dummies,
+            move_players,
+            pickup::collect_pickups,
+            aim_turrets,
+            projectile::fire_projectiles,
+            barricade::deploy_barricades,
+            ability::use_abilities,
+            smoke::dissipate_smoke,
+            projectile::move_projectiles,
+            barricade::remove_destroyed_barricades,
+            status::tick_status_effects,
+            waves::run_waves.run_if(resource_equals(MyGameMode::CoopWaves)),
+            health::respawn_destroyed_tanks,
+        ).chain())
         .run();
 }
 
@@ -81,6 +266,117 @@ struct MyScale(f32);
 #[derive(Resource, Default)]
 struct MyNumPlayers(u16);
 
+/// The tank class each player handle drives, handles without an entry get the default class
+#[derive(Resource, Default)]
+struct MyTankClasses(Vec<TankClass>);
+
+/// The tank class the local player picked in the menu
+#[derive(Resource, Default)]
+struct MyTankClass(TankClass);
+
+/// What the peers have told each other while waiting for the match to start
+#[derive(Resource)]
+struct Lobby {
+    /// the class each of the other peers picked
+    classes: HashMap<PeerId, TankClass>,
+    /// how long to wait for the room to fill up before bots take the handles nobody joined for
+    fill_wait: Timer,
+    /// whether the first player started the match without waiting for a full room
+    started: bool,
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Self {
+            classes: HashMap::default(),
+            fill_wait: Timer::from_seconds(BOT_FILL_WAIT, TimerMode::Once),
+            started: false,
+        }
+    }
+}
+
+/// Difficulty of the bots that take the player handles no peer joined for, `None` waits for a full room
+#[derive(Resource, Default)]
+struct MyBots(Option<bot::Difficulty>);
+
+/// Whether the game is still at the main menu, waiting for everyone to join or playing a match
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum AppState {
+    #[default]
+    Menu,
+    /// a match has been picked and we are waiting for the other peers
+    Connecting,
+    InGame,
+    /// editing a map, no match is running
+    Editor,
+}
+
+/// Which kind of match is being played
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+enum MyGameMode {
+    /// every tank for itself, destroyed tanks come straight back
+    #[default]
+    Deathmatch,
+    /// the players team up against waves of bots and stay down until the wave is cleared
+    CoopWaves,
+    /// an offline range with target dummies for trying out the tanks and weapons
+    Training,
+}
+
+/// The modes in the order the main menu lists them
+const GAME_MODES: [MyGameMode; 3] = [MyGameMode::Deathmatch, MyGameMode::CoopWaves, MyGameMode::Training];
+
+impl MyGameMode {
+    fn name(self) -> &'static str {
+        match self {
+            MyGameMode::Deathmatch => "Deathmatch",
+            MyGameMode::CoopWaves => "Co-op waves",
+            MyGameMode::Training => "Training range",
+        }
+    }
+
+    /// Matchbox room for the mode, so players only get matched with others who picked the same one
+    fn room(self) -> &'static str {
+        match self {
+            MyGameMode::Deathmatch => "extreme_bevy",
+            MyGameMode::CoopWaves => "extreme_bevy_coop",
+            MyGameMode::Training => "extreme_bevy_training",
+        }
+    }
+}
+
+/// This frame's input for every tank, the session's inputs for players followed by whatever the bots decided
+#[derive(Resource, Default)]
+struct TankInputs(Vec<u64>);
+
+impl TankInputs {
+    fn set(&mut self, handle: usize, input: u64) {
+        if self.0.len() <= handle {
+            self.0.resize(handle + 1, 0);
+        }
+        self.0[handle] = input;
+    }
+}
+
+impl std::ops::Index<usize> for TankInputs {
+    type Output = u64;
+
+    /// Tanks without an input this frame sit still
+    fn index(&self, handle: usize) -> &u64 {
+        self.0.get(handle).unwrap_or(&0)
+    }
+}
+
+/// Which control scheme the local player drives with
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+enum MyControlScheme {
+    /// W/S throttle and A/D rotate the hull in place
+    #[default]
+    Classic,
+    /// Q/A drive the left track and E/D drive the right track, or one stick per track
+    Tracks,
+}
+
 /// Used to help identify our main camera
 #[derive(Component)]
 struct MainCamera;
@@ -89,18 +385,42 @@ struct MainCamera;
 #[derive(Component)]
 struct Player {
     handle: usize,
-    /// linear speed in meters per second
+    /// top forward speed in meters per second
     movement_speed: f32,
+    /// top reverse speed in meters per second
+    max_reverse_speed: f32,
+    /// how quickly the engine builds up speed in meters per second squared
+    acceleration: f32,
+    /// deceleration when driving against the current direction of travel in meters per second squared
+    braking: f32,
+    /// deceleration when coasting with no throttle in meters per second squared
+    rolling_resistance: f32,
+    /// how quickly sideways drift is scrubbed off by the tracks in meters per second squared
+    lateral_friction: f32,
     /// rotation speed in radians per second
     rotation_speed: f32,
+    /// how far the crew can spot enemies in meters
+    view_range: f32,
 }
 
+/// Which side a tank fights for, tanks on the same team share vision
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct Team(usize);
+
+/// Current hull velocity in meters per second, kept as rollback state so momentum survives resimulation
+#[derive(Component, Clone, Copy, Default)]
+struct Velocity(Vec2);
+
 /// player component
-#[derive(Component)]
+#[derive(Component, Clone)]
 struct Turret {
     handle: usize,
     /// rotation speed in radians per second
     rotation_speed: f32,
+    /// current angle relative to the hull's forward direction in radians, counter clockwise positive
+    traverse: f32,
+    /// how far the turret can traverse to either side of the hull's forward direction in radians
+    traverse_limit: Option<f32>,
 }
 
 /// Target Reticle Component
@@ -109,6 +429,12 @@ struct Target {
     handle: usize,
 }
 
+/// Name shown above a tank
+#[derive(Component)]
+struct Nameplate {
+    handle: usize,
+}
+
 /// Initializes the player shapes and camera
 fn setup(
     mut commands: Commands,
@@ -163,43 +489,141 @@ fn setup(
 }
 
 /// Spawns the player sprite(s)
+#[allow(clippy::too_many_arguments)]
 fn spawn_players(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    my_num_players: ResMut<MyNumPlayers>,
+    my_num_players: Res<MyNumPlayers>,
+    my_tank_classes: Res<MyTankClasses>,
+    my_bots: Res<MyBots>,
+    game_mode: Res<MyGameMode>,
+    map: Res<MapData>,
+    socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
 ) {
-    for i in 0..my_num_players.0 {
+    let coop = *game_mode == MyGameMode::CoopWaves;
+    let training = *game_mode == MyGameMode::Training;
+    // the session has a handle for every peer that joined, the training range is played alone without a socket
+    let num_players = socket.map_or(1, |mut socket| socket.players().len());
+    // bots take the player handles nobody joined for, in every mode
+    let empty_slots = usize::from(my_num_players.0).saturating_sub(num_players);
+    let fill_bots = my_bots.0.map(|difficulty| vec![difficulty; empty_slots]).unwrap_or_default();
+    // in co-op a pool of enemy bots sits out until the waves call them in, the training range has dummies instead
+    let wave_bots = if coop { vec![bot::Difficulty::Easy; waves::WAVE_BOTS] } else { vec![] };
+    let dummies: &[training::DummyDef] = if training { &training::DUMMIES } else { &[] };
+
+    let num_tanks = num_players + fill_bots.len() + wave_bots.len() + dummies.len();
+    let mut taken = Vec::new();
+    for i in 0..num_tanks as u16 {
+        // handles after the session's players are driven by the bots filling in for players, then come the wave bots or
+        // the dummies on the training range
+        let bot_index = usize::from(i).checked_sub(num_players);
+        let fill_bot = bot_index.and_then(|bot| fill_bots.get(bot)).copied();
+        let extra_index = bot_index.and_then(|bot| bot.checked_sub(fill_bots.len()));
+        let wave_bot = extra_index.and_then(|bot| wave_bots.get(bot)).copied();
+        let dummy = extra_index.and_then(|dummy| dummies.get(dummy)).copied();
+        let difficulty = fill_bot.or(wave_bot);
+        let class = match dummy {
+            Some(dummy) => dummy.class,
+            None => my_tank_classes.0.get(usize::from(i)).copied().unwrap_or_default(),
+        };
+        let stats = class.stats();
+        // in co-op the players and the bots filling in for them are one team against the waves, on the training range
+        // the bots spar for the dummies' side, otherwise every tank fights for itself
+        let team = match *game_mode {
+            MyGameMode::CoopWaves if wave_bot.is_some() => waves::ENEMY_TEAM,
+            MyGameMode::CoopWaves => waves::PLAYER_TEAM,
+            MyGameMode::Training => Team(usize::from(bot_index.is_some())),
+            MyGameMode::Deathmatch => Team(usize::from(i)),
+        };
+
+        // everywhere the tank can start from and come back at
+        let spawn_points = match (bot_index, dummy) {
+            (_, Some(dummy)) => vec![dummy.position],
+            _ if wave_bot.is_some() => waves::bot_spawn_points(map.bounds),
+            (None, None) if training => vec![training::RANGE_START],
+            _ => spawn::team_spawns(&map.spawns, team.0),
+        };
+        // maps without spawn points line the tanks up along the middle
+        let spawn_points = if spawn_points.is_empty() {
+            vec![Vec2::new(f32::from(i) * 5., 0.)]
+        } else {
+            spawn_points
+        };
+        let spawn_point = spawn::safest_spawn(&spawn_points, &[], &taken).unwrap_or_default();
+        taken.push(spawn_point);
+        let spawn_point = Vec3::from((spawn_point, 100.));
+        let mut health = health::Health::new(stats.max_health);
+        if wave_bot.is_some() {
+            health.current = 0.;
+            health.respawn = false;
+        }
+
         // Rectangle
-        commands.spawn((
+        let mut hull = commands.spawn((
             SpriteBundle {
                 sprite: Sprite {
-                    color: Color::rgb(0.25, 0.25, 0.75),
-                    custom_size: Some(Vec2::new(2.0, 4.0)),
+                    color: if dummy.is_some() {
+                        Color::rgb(0.75, 0.65, 0.25)
+                    } else if difficulty.is_some() {
+                        Color::rgb(0.75, 0.25, 0.25)
+                    } else {
+                        Color::rgb(0.25, 0.25, 0.75)
+                    },
+                    custom_size: Some(HULL_SIZE),
                     ..default()
                 },
-            transform: Transform::from_translation(Vec3::new(0. + f32::from(i) * 5., 0., 100.)),
+            transform: Transform::from_translation(spawn_point),
             ..default()
             },
             Player {
                 handle: usize::from(i),
-                movement_speed: 10.0,                  // meters per second
-                rotation_speed: f32::to_radians(180.0), // degrees per second
+                movement_speed: stats.movement_speed,
+                max_reverse_speed: stats.max_reverse_speed,
+                acceleration: stats.acceleration,
+                braking: stats.braking,
+                rolling_resistance: stats.rolling_resistance,
+                lateral_friction: stats.lateral_friction,
+                rotation_speed: stats.rotation_speed,
+                view_range: stats.view_range,
             },
-        ))
-        .add_rollback();
+            team,
+            class,
+            Velocity::default(),
+            health,
+            spawn::SpawnPoints(spawn_points),
+            stats.armor,
+            Armament::default(),
+            pickup::PowerUps {
+                shield: spawn::SPAWN_PROTECTION,
+                ..default()
+            },
+            barricade::BarricadeLayer::default(),
+            ability::Abilities::new(stats.abilities),
+            status::StatusEffects::default(),
+            module::Modules::default(),
+        ));
+        if let Some(difficulty) = difficulty {
+            hull.insert(bot::Bot::new(difficulty));
+        }
+        if let Some(dummy) = dummy {
+            hull.insert(training::Dummy { moving: dummy.moving });
+        }
+        hull.add_rollback();
         
         // Triangle
         commands.spawn((
             MaterialMesh2dBundle {
             mesh: meshes.add(shape::RegularPolygon::new(1., 3).into()).into(),
             material: materials.add(ColorMaterial::from(Color::TURQUOISE)),
-            transform: Transform::from_translation(Vec3::new(0. + f32::from(i) * 5., 0., 101.)),
+            transform: Transform::from_translation(spawn_point.truncate().extend(101.)),
             ..default()
             },
             Turret {
                 handle: usize::from(i),
-                rotation_speed: f32::to_radians(180.0), // degrees per second
+                rotation_speed: stats.turret_rotation_speed,
+                traverse: 0.,
+                traverse_limit: stats.traverse_limit,
             },
         ))
         .add_rollback();
@@ -209,7 +633,7 @@ fn spawn_players(
             MaterialMesh2dBundle {
             mesh: meshes.add(shape::Circle::new(0.1).into()).into(),
             material: materials.add(ColorMaterial::from(Color::PURPLE)),
-            transform: Transform::from_translation(Vec3::new(0. + f32::from(i) *5., 0., 102.)),
+            transform: Transform::from_translation(spawn_point.truncate().extend(102.)),
             ..default()
         },
             Target {
@@ -217,27 +641,112 @@ fn spawn_players(
             },
         ));
         // .add_rollback();
+
+        // Name
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    match difficulty {
+                        _ if dummy.is_some_and(|dummy| dummy.moving) => format!("Moving dummy ({class:?})"),
+                        _ if dummy.is_some() => format!("Dummy ({class:?})"),
+                        // wave bots change difficulty from wave to wave
+                        _ if wave_bot.is_some() => format!("Enemy ({class:?})"),
+                        Some(difficulty) => format!("{difficulty:?} bot ({class:?})"),
+                        None => format!("Player {} ({:?})", i + 1, class),
+                    },
+                    TextStyle {
+                        font_size: 20.,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                transform: Transform::from_translation(spawn_point + NAMEPLATE_OFFSET)
+                    .with_scale(Vec3::splat(NAMEPLATE_SCALE)),
+                ..default()
+            },
+            Nameplate {
+                handle: usize::from(i),
+            },
+        ));
     }
 }
 
 /// Starts the matchbox socket to connect to the matchmaking server
-fn start_matchbox_socket(mut commands: Commands) {
-    let room_url = "ws://127.0.0.1:3536/extreme_bevy?next=2";
+fn start_matchbox_socket(
+    mut commands: Commands,
+    game_mode: Res<MyGameMode>,
+    map_choice: Res<map::MyMapChoice>,
+    my_num_players: Res<MyNumPlayers>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    // players who want a generated map get their own room, and custom maps get a room per map so everyone in it has
+    // the same one
+    let map_suffix = match *map_choice {
+        map::MyMapChoice::Arena => String::new(),
+        map::MyMapChoice::Generated => "_random".to_string(),
+        map::MyMapChoice::Custom => match MapData::load(map::CUSTOM_MAP_PATH) {
+            Ok(map) => {
+                let suffix = format!("_custom_{:016x}", map.fingerprint());
+                commands.insert_resource(map::CustomMap(map));
+                suffix
+            }
+            Err(err) => {
+                error!("can't play the custom map: {err}");
+                next_state.set(AppState::Menu);
+                return;
+            }
+        },
+    };
+    let room_url = format!("ws://127.0.0.1:3536/{}{map_suffix}?next={}", game_mode.room(), my_num_players.0);
     info!("connecting to matchbox server: {room_url}");
-    commands.insert_resource(MatchboxSocket::new_ggrs(room_url));
+    let socket = WebRtcSocketBuilder::new(room_url).add_ggrs_channel().add_reliable_channel();
+    commands.insert_resource(MatchboxSocket::from(socket));
+    commands.insert_resource(Lobby::default());
 }
 
 /// Draws UI elements you don't need other players to see
 fn draw_client_side(
     player_query: Query<(&Player, &Transform)>,
+    fog: Res<fog::FogOfWar>,
     mut gizmos: Gizmos,
 ){
-    for (_ship, ship_transform) in &player_query {
+    for (ship, ship_transform) in &player_query {
+        if !fog.visible_handles.contains(&ship.handle) {
+            continue;
+        }
         let ship_pos = ship_transform.translation.xy();
         gizmos.circle_2d(ship_pos, 10., Color::GREEN);
     }
 }
 
+/// Copies the session's inputs for this frame so bots can add theirs alongside
+///
+/// Tanks that are down and waiting to come back get no input.
+fn collect_inputs(
+    inputs: Res<PlayerInputs<Config>>,
+    mut tank_inputs: ResMut<TankInputs>,
+    player_query: Query<(&Player, &health::Health)>,
+) {
+    tank_inputs.0 = inputs.iter().map(|(input, _)| *input).collect();
+    for (ship, health) in &player_query {
+        if health.current <= 0. && ship.handle < tank_inputs.0.len() {
+            tank_inputs.0[ship.handle] = 0;
+        }
+    }
+}
+
+/// Keeps each nameplate above its tank
+fn place_nameplates(
+    player_query: Query<(&Player, &Transform)>,
+    mut nameplate_query: Query<(&Nameplate, &mut Transform), Without<Player>>,
+) {
+    for (nameplate, mut nameplate_transform) in &mut nameplate_query {
+        if let Some((_, ship_transform)) = player_query.iter().find(|(ship, _)| ship.handle == nameplate.handle) {
+            nameplate_transform.translation = ship_transform.translation + NAMEPLATE_OFFSET;
+        }
+    }
+}
+
 /// Allows for camera zoom
 fn zoom_scalingmode(
     mut query_camera: Query<&mut OrthographicProjection, With<MainCamera>>,
@@ -272,30 +781,91 @@ fn zoom_scalingmode(
     }
 }
 
-/// Sets up the GGRS peer connection once two players are in the server
+/// Goes in-game once the room is full and every player has told us its class, agreeing on the map seed on the way
+///
+/// With bots on, the first player in handle order starts the match for everyone who joined once the room has had a while
+/// to fill up, and bots take the other handles.
+#[allow(clippy::too_many_arguments)]
 fn wait_for_players(
     mut commands: Commands,
-    mut socket: ResMut<MatchboxSocket<SingleChannel>>,
-    my_num_players: ResMut<MyNumPlayers>,
+    time: Res<Time>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    my_num_players: Res<MyNumPlayers>,
+    my_bots: Res<MyBots>,
+    my_tank_class: Res<MyTankClass>,
+    mut lobby: ResMut<Lobby>,
+    mut next_state: ResMut<NextState<AppState>>,
 ){
-    if socket.get_channel(0).is_err() {
-        return; // we've already started
+    // Check for new connections, and tell every new peer which class we picked
+    for (peer, state) in socket.update_peers() {
+        if state == PeerState::Connected {
+            socket.channel_mut(LOBBY_CHANNEL).send(Box::new([my_tank_class.0.to_byte()]), peer);
+        }
     }
-    
-    // Check for new connections
-    socket.update_peers();
+    for (peer, packet) in socket.channel_mut(LOBBY_CHANNEL).receive() {
+        match packet.first().copied() {
+            Some(LOBBY_START) => lobby.started = true,
+            byte => match byte.and_then(TankClass::from_byte) {
+                Some(class) => {
+                    lobby.classes.insert(peer, class);
+                }
+                None => warn!("peer {peer} sent a lobby message we don't know: {packet:?}"),
+            },
+        }
+    }
+    lobby.fill_wait.tick(time.delta());
     let players = socket.players();
 
-    let num_players = usize::from(my_num_players.0);
-    if players.len() < num_players {
+    let full = players.len() >= usize::from(my_num_players.0);
+    let first = players.first() == Some(&ggrs::PlayerType::Local);
+    let starting = lobby.started || (first && my_bots.0.is_some() && lobby.fill_wait.finished());
+    if !full && !starting {
         return; // wait for more players
     }
 
-    info!("All peers have joined, going in-game");
+    // the classes line up with the session's handles, which follow the order of the players
+    let classes: Option<Vec<TankClass>> = players
+        .iter()
+        .map(|player| match player {
+            ggrs::PlayerType::Local => Some(my_tank_class.0),
+            ggrs::PlayerType::Remote(peer) => lobby.classes.get(peer).copied(),
+            ggrs::PlayerType::Spectator(_) => None,
+        })
+        .collect();
+    let Some(classes) = classes else {
+        return; // wait for every peer's class
+    };
+    commands.insert_resource(MyTankClasses(classes));
+
+    if !full && first {
+        for peer in socket.connected_peers().collect::<Vec<_>>() {
+            socket.channel_mut(LOBBY_CHANNEL).send(Box::new([LOBBY_START]), peer);
+        }
+    }
+
+    info!("{} of {} players have joined, going in-game", players.len(), my_num_players.0);
+
+    // every peer knows everyone's id by now, so they all come up with the same seed without having to send one
+    let peers: Vec<u128> = socket
+        .id()
+        .into_iter()
+        .chain(socket.connected_peers())
+        .map(|peer| peer.0.as_u128())
+        .collect();
+    commands.insert_resource(mapgen::MapSeed::from_peers(peers));
+    next_state.set(AppState::InGame);
+}
 
-    // create a GGRS P2P session
+/// Sets up the GGRS peer connection once the world has been built
+fn start_p2p_session(
+    mut commands: Commands,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+) {
+    let players = socket.players();
+
+    // create a GGRS P2P session, with a handle for every peer that joined
     let mut session_builder = ggrs::SessionBuilder::<Config>::new()
-        .with_num_players(num_players)
+        .with_num_players(players.len())
         .with_input_delay(1);
 
     for (i, player) in players.into_iter().enumerate() {
@@ -305,7 +875,7 @@ fn wait_for_players(
     }
 
     // move the channel out of the socket (required because GGRS takes ownership of it)
-    let channel = socket.take_channel(0).unwrap();
+    let channel = socket.take_channel(GGRS_CHANNEL).unwrap();
 
     // start the GGRS session
     let ggrs_session = session_builder
@@ -340,31 +910,93 @@ fn my_cursor_system(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn read_local_inputs(
     mut commands: Commands,
     keys: Res<Input<KeyCode>>,
     local_players: Res<LocalPlayers>,
     mb: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    control_scheme: Res<MyControlScheme>,
+    selected_weapon: Res<MySelectedWeapon>,
+    mouse_cords: Res<MyWorldCoords>,
+    mut minimap_input: ResMut<minimap::MinimapInput>,
 ) {
     let mut local_inputs = HashMap::new();
+    let ping = minimap_input.ping.take();
 
     for handle in &local_players.0 {
-        let mut input = 0u8;
-
-        if keys.any_pressed([KeyCode::Up, KeyCode::W]) {
-            input |= INPUT_FORWARD;
+        let mut input = 0u64;
+
+        match *control_scheme {
+            MyControlScheme::Classic => {
+                if keys.any_pressed([KeyCode::Up, KeyCode::W]) {
+                    input |= INPUT_FORWARD;
+                }
+                if keys.any_pressed([KeyCode::Down, KeyCode::S]) {
+                    input |= INPUT_REVERSE;
+                }
+                if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
+                    input |= INPUT_LEFT
+                }
+                if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
+                    input |= INPUT_RIGHT;
+                }
+            }
+            MyControlScheme::Tracks => {
+                if keys.pressed(KeyCode::Q) {
+                    input |= INPUT_LEFT_TRACK_FORWARD;
+                }
+                if keys.pressed(KeyCode::A) {
+                    input |= INPUT_LEFT_TRACK_REVERSE;
+                }
+                if keys.pressed(KeyCode::E) {
+                    input |= INPUT_RIGHT_TRACK_FORWARD;
+                }
+                if keys.pressed(KeyCode::D) {
+                    input |= INPUT_RIGHT_TRACK_REVERSE;
+                }
+
+                // Left stick drives the left track and right stick drives the right track
+                for gamepad in gamepads.iter() {
+                    let left = axes
+                        .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+                        .unwrap_or_default();
+                    let right = axes
+                        .get(GamepadAxis::new(gamepad, GamepadAxisType::RightStickY))
+                        .unwrap_or_default();
+
+                    if left > STICK_DEADZONE {
+                        input |= INPUT_LEFT_TRACK_FORWARD;
+                    } else if left < -STICK_DEADZONE {
+                        input |= INPUT_LEFT_TRACK_REVERSE;
+                    }
+                    if right > STICK_DEADZONE {
+                        input |= INPUT_RIGHT_TRACK_FORWARD;
+                    } else if right < -STICK_DEADZONE {
+                        input |= INPUT_RIGHT_TRACK_REVERSE;
+                    }
+                }
+            }
         }
-        if keys.any_pressed([KeyCode::Down, KeyCode::S]) {
-            input |= INPUT_REVERSE;
+        // clicks on the minimap place pings instead of firing
+        if mb.any_pressed([MouseButton::Left]) && !minimap_input.hovered {
+            input |= INPUT_FIRE;
         }
-        if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
-            input |= INPUT_LEFT
+        if keys.pressed(KeyCode::B) {
+            input |= barricade::INPUT_BARRICADE;
         }
-        if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
-            input |= INPUT_RIGHT;
+        if keys.pressed(KeyCode::F) {
+            input |= ability::INPUT_ABILITY_1;
         }
-        if mb.any_pressed([MouseButton::Left]) {
-            input |= INPUT_FIRE;
+        if keys.pressed(KeyCode::G) {
+            input |= ability::INPUT_ABILITY_2;
+        }
+        input |= selected_weapon.0.to_input();
+        input |= encode_aim(mouse_cords.0);
+        if let Some(ping) = ping {
+            input |= minimap::encode_ping(ping);
         }
 
         local_inputs.insert(*handle, input);
@@ -373,21 +1005,37 @@ fn read_local_inputs(
     commands.insert_resource(LocalInputs::<Config>(local_inputs));
 }
 
+/// Switches the local player between the classic and the per-track control schemes
+fn toggle_control_scheme(
+    keys: Res<Input<KeyCode>>,
+    mut control_scheme: ResMut<MyControlScheme>,
+) {
+    if keys.just_pressed(KeyCode::Tab) {
+        *control_scheme = match *control_scheme {
+            MyControlScheme::Classic => MyControlScheme::Tracks,
+            MyControlScheme::Tracks => MyControlScheme::Classic,
+        };
+        info!("control scheme: {:?}", *control_scheme);
+    }
+}
+
 fn move_players(
-    inputs: Res<PlayerInputs<Config>>,
+    inputs: Res<TankInputs>,
     time: Res<Time>,
-    mut player_query: Query<(&Player, &mut Transform), With<Player>>,
-    mut target_query: Query<(&Target, &mut Transform), Without<Player>>,
-    mut turret_query: Query<(&Turret, &mut Transform), (Without<Player>, Without<Target>)>,
-    local_players: Res<LocalPlayers>,
-    mouse_cords: Res<MyWorldCoords>,
+    map: Res<MapData>,
+    mut player_query: Query<(
+        &Player,
+        &mut Transform,
+        &mut Velocity,
+        &pickup::PowerUps,
+        &status::StatusEffects,
+        &module::Modules,
+    )>,
+    obstacle_query: Query<(&Obstacle, &Transform, Option<&Destructible>), Without<Player>>,
 ) {
-    let mut body_pos = HashMap::new();
-    let mut tar_pos = HashMap::new();
-
     // Body handling
-    for (ship, mut ship_transform) in &mut player_query {
-        let (input, _) = inputs[ship.handle];
+    for (ship, mut ship_transform, mut velocity, power_ups, status, modules) in &mut player_query {
+        let input = status.allowed_input(inputs[ship.handle]);
 
         let mut rotation_factor = 0.0;
         let mut movement_factor = 0.0;
@@ -405,192 +1053,185 @@ fn move_players(
             rotation_factor += 1.0;
         }
 
-        // update the ship rotation around the Z axis (perpendicular to the 2D plane of the screen)
-        ship_transform.rotate_z(rotation_factor * ship.rotation_speed * time.delta_seconds());
-
-        // get the ship's forward vector by applying the current rotation to the ships initial facing vector
-        let movement_direction = ship_transform.rotation * Vec3::Y;
-        // get the distance the ship will move based on direction, the ship's movement speed and delta time
-        let movement_distance = movement_factor * ship.movement_speed * time.delta_seconds();
-        // create the change in translation using the new movement direction and distance
-        let translation_delta = movement_direction * movement_distance;
-        // update the ship translation with our new translation delta
-        ship_transform.translation += translation_delta;
-
-        // bound the ship within the invisible level bounds
-        let extents = Vec3::from((BOUNDS / 2.0, 0.0));
-        ship_transform.translation = ship_transform.translation.min(extents).max(-extents);
-        body_pos.insert(ship.handle, ship_transform.translation);
-    }
-
-    // Target Handling
-    for (target, mut tar_transform) in &mut target_query{
-        // If it's a local player update the target to the mouse position
-        if local_players.0.contains(&target.handle){
-            tar_transform.translation = Vec3::from((mouse_cords.0, 102.));
+        // with differential steering each track is driven on its own, the hull moves with the average
+        // of the two tracks and turns with their difference
+        let left_track = track_factor(input, INPUT_LEFT_TRACK_FORWARD, INPUT_LEFT_TRACK_REVERSE);
+        let right_track = track_factor(input, INPUT_RIGHT_TRACK_FORWARD, INPUT_RIGHT_TRACK_REVERSE);
+        if left_track != 0.0 || right_track != 0.0 {
+            movement_factor = (left_track + right_track) / 2.0;
+            rotation_factor = (right_track - left_track).clamp(-1.0, 1.0);
         }
 
-        // Save the target position to be used for pointing the turret later
-        tar_pos.insert(target.handle, tar_transform.translation);
-
-        // let (input, _) = inputs[target.handle];
-        // match input{
-        //     0 => (),
-        //     _ => tar_transform.translation = Vec3::from((mouse_cords.0, 102.)),
-        // }
-    }
-
-    // Turret Handling
-    for (turret, mut tur_transform) in &mut turret_query {
-        // If the body moved then first move the turret along with it
-        match body_pos.get(&turret.handle){
-            Some(&trans) => tur_transform.translation = Vec3::from((trans.truncate(), 101.)),
-            _ => (),
-        }
-
-        // Get the matching target for the given turret and save it's translation for later
-        let mut target_translation = Vec3::splat(0.);
-
-        match tar_pos.get(&turret.handle){
-            Some(&trans) => target_translation = Vec3::from((trans.truncate(), 101.)),
-            _ => (),
-        }
-
-        // tur_transform.translation = Vec3::from((body_pos.get(&turret.handle).truncate(), 101.));
-        // get the enemy ship forward vector in 2D (already unit length)
-        let turret_forward = (tur_transform.rotation * Vec3::Y).xy();
-
-        // get the vector from the turret to the target in 2D and normalize it.
-        let to_target = (target_translation.xy() - tur_transform.translation.xy()).normalize();
+        let delta = time.delta_seconds();
 
-        // get the dot product between the enemy forward vector and the direction to the player.
-        let forward_dot_target = turret_forward.dot(to_target);
+        // the ground under the centre of the hull changes how the tank handles, a speed boost helps on any ground
+        // and a damaged engine or tracks hold it back
+        let terrain = map.terrain.at(ship_transform.translation.xy()).modifiers();
+        let boost = power_ups.speed_factor() * modules.speed_factor();
+        let movement_speed = ship.movement_speed * terrain.max_speed * boost;
+        let max_reverse_speed = ship.max_reverse_speed * terrain.max_speed * boost;
+        let acceleration = ship.acceleration * terrain.acceleration * boost;
+        let braking = ship.braking * terrain.traction;
+        let lateral_friction = ship.lateral_friction * terrain.traction;
 
-        // if the dot product is approximately 1.0 then the turret is already facing the target and we can early out.
-        if !((forward_dot_target - 1.0).abs() < f32::EPSILON) {
-            // get the right vector of the turret in 2D (already unit length)
-            let tur_right = (tur_transform.rotation * Vec3::X).xy();
-
-            // get the dot product of the enemy right vector and the direction to the player ship.
-            // if the dot product is negative them we need to rotate counter clockwise, if it is
-            // positive we need to rotate clockwise. Note that `copysign` will still return 1.0 if the
-            // dot product is 0.0 (because the player is directly behind the enemy, so perpendicular
-            // with the right vector).
-            let right_dot_target = tur_right.dot(to_target);
-
-            // determine the sign of rotation from the right dot target. We need to negate the sign
-            // here as the 2D bevy co-ordinate system rotates around +Z, which is pointing out of the
-            // screen. Due to the right hand rule, positive rotation around +Z is counter clockwise and
-            // negative is clockwise.
-            let rotation_sign = -f32::copysign(1.0, right_dot_target);
+        // update the ship rotation around the Z axis (perpendicular to the 2D plane of the screen)
+        ship_transform.rotate_z(rotation_factor * ship.rotation_speed * modules.track_factor() * delta);
+
+        // split the current velocity into the part along the hull and the part sliding sideways
+        let forward = (ship_transform.rotation * Vec3::Y).xy();
+        let right = (ship_transform.rotation * Vec3::X).xy();
+        let mut forward_speed = velocity.0.dot(forward);
+        let mut lateral_speed = velocity.0.dot(right);
+
+        // throttle towards top speed, brake when the throttle opposes the direction of travel and coast otherwise
+        forward_speed = if movement_factor > 0.0 {
+            if forward_speed < 0.0 {
+                move_towards(forward_speed, 0.0, braking * delta)
+            } else if forward_speed > movement_factor * movement_speed {
+                // driving onto slower ground bleeds off the extra speed like braking would
+                move_towards(forward_speed, movement_factor * movement_speed, braking * delta)
+            } else {
+                move_towards(forward_speed, movement_factor * movement_speed, acceleration * delta)
+            }
+        } else if movement_factor < 0.0 {
+            if forward_speed > 0.0 {
+                move_towards(forward_speed, 0.0, braking * delta)
+            } else {
+                move_towards(forward_speed, movement_factor * max_reverse_speed, acceleration * delta)
+            }
+        } else {
+            move_towards(forward_speed, 0.0, ship.rolling_resistance * delta)
+        };
 
-            // limit rotation so we don't overshoot the target. We need to convert our dot product to
-            // an angle here so we can get an angle of rotation to clamp against.
-            let max_angle = forward_dot_target.clamp(-1.0, 1.0).acos(); // clamp acos for safety
+        // the tracks resist sliding sideways, anything left over is drift
+        lateral_speed = move_towards(lateral_speed, 0.0, lateral_friction * delta);
 
-            // calculate angle of rotation with limit
-            let rotation_angle =
-                rotation_sign * (turret.rotation_speed * time.delta_seconds()).min(max_angle);
+        velocity.0 = forward * forward_speed + right * lateral_speed;
+        // update the ship translation with our new velocity
+        ship_transform.translation += Vec3::from((velocity.0 * delta, 0.0));
 
-            // rotate the turret to face the target
-            tur_transform.rotate_z(rotation_angle);
+        // push the hull back out of any obstacle it drove into and stop it pushing further in, the walls around the
+        // edge of the map included
+        for (obstacle, obstacle_transform, destructible) in &obstacle_query {
+            if !is_standing(destructible) {
+                continue;
+            }
+            let hull = OrientedRect::from_transform(&ship_transform, HULL_SIZE);
+            if let Some(push) = hull.separation(&obstacle.rect(obstacle_transform)) {
+                ship_transform.translation += Vec3::from((push, 0.));
+                let normal = push.normalize_or_zero();
+                let into_obstacle = velocity.0.dot(normal);
+                if into_obstacle < 0. {
+                    velocity.0 -= normal * into_obstacle;
+                }
+            }
         }
     }
 }
 
-/// Demonstrates applying rotation and movement based on keyboard input.
-fn player_movement_system(
+type TurretQuery<'w, 's> = Query<'w, 's, (&'static mut Turret, &'static mut Transform), (Without<Player>, Without<Target>)>;
+
+/// Moves the target reticles and traverses each turret towards its target
+fn aim_turrets(
+    inputs: Res<TankInputs>,
     time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
-    mut player_query: Query<(&Player, &mut Transform), With<Player>>,
+    player_query: Query<(&Player, &Transform, &status::StatusEffects, &module::Modules)>,
     mut target_query: Query<(&Target, &mut Transform), Without<Player>>,
-    mut turret_query: Query<(&Turret, &mut Transform), (Without<Player>, Without<Target>)>,
-    mouse_cords: Res<MyWorldCoords>,
+    mut turret_query: TurretQuery,
 ) {
-    let (ship, mut ship_transform) = player_query.single_mut();
-    let (_target, mut tar_transform) = target_query.single_mut();
-    let (turret, mut tur_transform) = turret_query.single_mut();
-    
-    let target_translation = tar_transform.translation.xy();
-
-    let mut rotation_factor = 0.0;
-    let mut movement_factor = 0.0;
-
-    if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
-        rotation_factor += 1.0;
-    }
-
-    if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
-        rotation_factor -= 1.0;
-    }
+    let body_pos: HashMap<_, _> = player_query
+        .iter()
+        .map(|(ship, ship_transform, status, modules)| {
+            let traverse_factor = if status.jammed() { 0. } else { modules.traverse_factor() };
+            (ship.handle, (ship_transform.translation, ship_transform.rotation, traverse_factor))
+        })
+        .collect();
+    let mut tar_pos = HashMap::new();
 
-    if keys.any_pressed([KeyCode::Up, KeyCode::W]) {
-        movement_factor += 1.0;
-    }
+    // Target Handling
+    for (target, mut tar_transform) in &mut target_query{
+        // The aim point is part of every player's input, so every peer puts the reticle in the same place
+        let input = inputs[target.handle];
+        tar_transform.translation = Vec3::from((decode_aim(input), 102.));
 
-    if keys.any_pressed([KeyCode::Down, KeyCode::S]) {
-        movement_factor -= 1.0;
+        // Save the target position to be used for pointing the turret later
+        tar_pos.insert(target.handle, tar_transform.translation);
     }
 
-    // update the ship rotation around the Z axis (perpendicular to the 2D plane of the screen)
-    ship_transform.rotate_z(rotation_factor * ship.rotation_speed * time.delta_seconds());
-
-    // get the ship's forward vector by applying the current rotation to the ships initial facing vector
-    let movement_direction = ship_transform.rotation * Vec3::Y;
-    // get the distance the ship will move based on direction, the ship's movement speed and delta time
-    let movement_distance = movement_factor * ship.movement_speed * time.delta_seconds();
-    // create the change in translation using the new movement direction and distance
-    let translation_delta = movement_direction * movement_distance;
-    // update the ship translation with our new translation delta
-    ship_transform.translation += translation_delta;
-
-    // bound the ship within the invisible level bounds
-    let extents = Vec3::from((BOUNDS / 2.0, 0.0));
-    ship_transform.translation = ship_transform.translation.min(extents).max(-extents);
-
-    // Target Handling
-    tar_transform.translation = Vec3::from((mouse_cords.0, 102.));
-
     // Turret Handling
-    tur_transform.translation = Vec3::from((ship_transform.translation.truncate(), 101.));
-    // get the enemy ship forward vector in 2D (already unit length)
-    let turret_forward = (tur_transform.rotation * Vec3::Y).xy();
-
-    // get the vector from the enemy ship to the player ship in 2D and normalize it.
-    let to_target = (target_translation - tur_transform.translation.xy()).normalize();
-
-    // get the dot product between the enemy forward vector and the direction to the player.
-    let forward_dot_target = turret_forward.dot(to_target);
+    for (mut turret, mut tur_transform) in &mut turret_query {
+        // The turret sits on the hull, so it follows both the hull's position and its rotation
+        let Some(&(hull_translation, hull_rotation, traverse_factor)) = body_pos.get(&turret.handle) else {
+            continue;
+        };
+        tur_transform.translation = Vec3::from((hull_translation.truncate(), 101.));
+
+        // Get the matching target for the given turret and the direction to it from the hull
+        if let Some(&target_translation) = tar_pos.get(&turret.handle) {
+            let to_target = target_translation.xy() - hull_translation.xy();
+
+            if to_target != Vec2::ZERO {
+                // angle of the target relative to the hull's forward vector, counter clockwise positive
+                let hull_forward = (hull_rotation * Vec3::Y).xy();
+                let desired = hull_forward.angle_between(to_target);
+
+                // a turret with a traverse arc stops at the edge of the arc and can't swing through the
+                // back of the hull, a fully rotating turret takes the shortest way around
+                let remaining = match turret.traverse_limit {
+                    Some(limit) => desired.clamp(-limit, limit) - turret.traverse,
+                    None => wrap_angle(desired - turret.traverse),
+                };
+
+                // limit rotation so we don't overshoot the target, a damaged turret ring turns slower and a
+                // jammed one not at all
+                let max_step = turret.rotation_speed * traverse_factor * time.delta_seconds();
+                turret.traverse = wrap_angle(turret.traverse + remaining.clamp(-max_step, max_step));
+            }
+        }
 
-    // if the dot product is approximately 1.0 then the enemy is already facing the player and
-    // we can early out.
-    if !((forward_dot_target - 1.0).abs() < f32::EPSILON) {
-        // get the right vector of the enemy ship in 2D (already unit length)
-        let tur_right = (tur_transform.rotation * Vec3::X).xy();
+        // the turret's world rotation is the hull's rotation plus its own traverse
+        tur_transform.rotation = hull_rotation * Quat::from_rotation_z(turret.traverse);
+    }
+}
 
-        // get the dot product of the enemy right vector and the direction to the player ship.
-        // if the dot product is negative them we need to rotate counter clockwise, if it is
-        // positive we need to rotate clockwise. Note that `copysign` will still return 1.0 if the
-        // dot product is 0.0 (because the player is directly behind the enemy, so perpendicular
-        // with the right vector).
-        let right_dot_target = tur_right.dot(to_target);
+/// Wraps an angle in radians into the range -PI..=PI
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
 
-        // determine the sign of rotation from the right dot player. We need to negate the sign
-        // here as the 2D bevy co-ordinate system rotates around +Z, which is pointing out of the
-        // screen. Due to the right hand rule, positive rotation around +Z is counter clockwise and
-        // negative is clockwise.
-        let rotation_sign = -f32::copysign(1.0, right_dot_target);
+/// Packs an aim point into the high bytes of an input
+fn encode_aim(aim: Vec2) -> u64 {
+    // `as` saturates, so aiming beyond the encodable range pins the aim to its edge
+    let x = (aim.x / AIM_RESOLUTION).round() as i16 as u16;
+    let y = (aim.y / AIM_RESOLUTION).round() as i16 as u16;
+    (u64::from(x) << INPUT_AIM_X_SHIFT) | (u64::from(y) << INPUT_AIM_Y_SHIFT)
+}
 
-        // limit rotation so we don't overshoot the target. We need to convert our dot product to
-        // an angle here so we can get an angle of rotation to clamp against.
-        let max_angle = forward_dot_target.clamp(-1.0, 1.0).acos(); // clamp acos for safety
+/// Unpacks the aim point from an input
+fn decode_aim(input: u64) -> Vec2 {
+    let x = (input >> INPUT_AIM_X_SHIFT) as u16 as i16;
+    let y = (input >> INPUT_AIM_Y_SHIFT) as u16 as i16;
+    Vec2::new(f32::from(x), f32::from(y)) * AIM_RESOLUTION
+}
 
-        // calculate angle of rotation with limit
-        let rotation_angle =
-            rotation_sign * (turret.rotation_speed * time.delta_seconds()).min(max_angle);
+/// Turns a pair of track input bits into a track drive factor of -1, 0 or 1
+fn track_factor(input: u64, forward: u64, reverse: u64) -> f32 {
+    let mut factor = 0.0;
+    if input & forward != 0 {
+        factor += 1.0;
+    }
+    if input & reverse != 0 {
+        factor -= 1.0;
+    }
+    factor
+}
 
-        // rotate the enemy to face the player
-        tur_transform.rotate_z(rotation_angle);
+/// Moves `current` towards `target` by at most `max_delta` without overshooting
+fn move_towards(current: f32, target: f32, max_delta: f32) -> f32 {
+    if (target - current).abs() <= max_delta {
+        target
+    } else {
+        current + f32::copysign(max_delta, target - current)
     }
 }
 
@@ -612,4 +1253,84 @@ fn player_movement_system(
 //             transform.translation.y = pos.y;
 //         }
 //     }
-// }
\ No newline at end of file
+// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_bits_drive_each_track_on_its_own() {
+        let input = INPUT_LEFT_TRACK_FORWARD | INPUT_RIGHT_TRACK_REVERSE;
+        assert_eq!(track_factor(input, INPUT_LEFT_TRACK_FORWARD, INPUT_LEFT_TRACK_REVERSE), 1.);
+        assert_eq!(track_factor(input, INPUT_RIGHT_TRACK_FORWARD, INPUT_RIGHT_TRACK_REVERSE), -1.);
+        // pressing both ways cancels out
+        let both = INPUT_LEFT_TRACK_FORWARD | INPUT_LEFT_TRACK_REVERSE;
+        assert_eq!(track_factor(both, INPUT_LEFT_TRACK_FORWARD, INPUT_LEFT_TRACK_REVERSE), 0.);
+        assert_eq!(track_factor(0, INPUT_LEFT_TRACK_FORWARD, INPUT_LEFT_TRACK_REVERSE), 0.);
+    }
+
+    #[test]
+    fn track_bits_stay_clear_of_the_movement_bits() {
+        let movement = INPUT_FORWARD | INPUT_REVERSE | INPUT_LEFT | INPUT_RIGHT | INPUT_FIRE;
+        for track in [
+            INPUT_LEFT_TRACK_FORWARD,
+            INPUT_LEFT_TRACK_REVERSE,
+            INPUT_RIGHT_TRACK_FORWARD,
+            INPUT_RIGHT_TRACK_REVERSE,
+        ] {
+            assert_eq!(track.count_ones(), 1);
+            assert_eq!(track & movement, 0);
+        }
+    }
+
+    #[test]
+    fn aim_round_trips_through_the_input() {
+        for aim in [Vec2::ZERO, Vec2::new(12.3, -45.6), Vec2::new(-300., 250.), Vec2::splat(-0.1)] {
+            let decoded = decode_aim(encode_aim(aim));
+            assert!(decoded.distance(aim) < AIM_RESOLUTION, "{aim} came back as {decoded}");
+        }
+    }
+
+    #[test]
+    fn aim_stays_in_the_high_bytes() {
+        let aim = encode_aim(Vec2::new(-1000., -0.1));
+        assert_eq!(aim & 0xffff_ffff, 0);
+        assert_eq!(decode_aim(aim | 0xffff_ffff), decode_aim(aim));
+    }
+
+    #[test]
+    fn aim_beyond_the_range_is_pinned_to_its_edge() {
+        let edge = f32::from(i16::MAX) * AIM_RESOLUTION;
+        let decoded = decode_aim(encode_aim(Vec2::new(1e6, -1e6)));
+        assert!((decoded.x - edge).abs() < 1e-3);
+        assert!((decoded.y + edge + AIM_RESOLUTION).abs() < 1e-3);
+    }
+
+    #[test]
+    fn input_fields_dont_overlap() {
+        let fields = [
+            INPUT_FORWARD,
+            INPUT_REVERSE,
+            INPUT_LEFT,
+            INPUT_RIGHT,
+            INPUT_FIRE,
+            INPUT_LEFT_TRACK_FORWARD,
+            INPUT_LEFT_TRACK_REVERSE,
+            INPUT_RIGHT_TRACK_FORWARD,
+            INPUT_RIGHT_TRACK_REVERSE,
+            weapon::INPUT_WEAPON_MASK,
+            barricade::INPUT_BARRICADE,
+            ability::INPUT_ABILITY_1,
+            ability::INPUT_ABILITY_2,
+            minimap::INPUT_PING,
+            minimap::encode_ping(Vec2::splat(1e6)) & !minimap::INPUT_PING,
+            encode_aim(Vec2::splat(-0.1)),
+        ];
+        let mut seen = 0;
+        for field in fields {
+            assert_eq!(seen & field, 0, "{field:#x} overlaps another input field");
+            seen |= field;
+        }
+    }
+}
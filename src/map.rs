@@ -0,0 +1,423 @@
+use bevy::prelude::*;
+
+use crate::mapgen::{self, MapGenParams, MapSeed};
+use crate::objective::{ObjectiveDef, DEFAULT_OBJECTIVE_RADIUS};
+use crate::obstacle::{ObstacleDef, ObstacleKind};
+use crate::pickup::{PickupDef, PickupKind, DEFAULT_RESPAWN_TIME};
+use crate::spawn::SpawnDef;
+use crate::terrain::{TerrainGrid, TerrainType};
+use crate::{BOUNDS, MAP_SIZE};
+
+/// The arena every peer loads, baked into the binary so all peers agree on it
+const DEFAULT_MAP: &str = include_str!("../assets/maps/arena.map");
+/// How thick the walls around the playable area are in meters, thick enough that nothing gets through in one frame
+const BOUNDARY_THICKNESS: f32 = 5.;
+/// Where the map editor saves to, and where custom maps are played from
+pub const CUSTOM_MAP_PATH: &str = "assets/maps/custom.map";
+
+/// Everything a map file describes
+///
+/// Map files are plain text, one directive per line, `#` starts a comment:
+/// - `terrain <type> <x> <y> <width> <height>` paints a rectangle of tiles, in tile coordinates
+/// - `obstacle <kind> <x> <y> <width> <height> [health]` places a block centred on a world position,
+///   obstacles with health can be destroyed
+/// - `pickup <kind> <x> <y> [respawn seconds]` places a pickup spawn point on a world position
+/// - `spawn <x> <y> [team]` places a spawn point on a world position, spawn points with a team are kept for that team
+/// - `objective <x> <y> [radius]` marks a circular area worth fighting over around a world position
+/// - `bounds <width> <height>` sets the size of the playable area around the centre of the map in meters, it is walled
+///   in all the way round
+#[derive(Resource, Clone)]
+pub struct MapData {
+    pub terrain: TerrainGrid,
+    pub obstacles: Vec<ObstacleDef>,
+    pub pickups: Vec<PickupDef>,
+    pub spawns: Vec<SpawnDef>,
+    pub objectives: Vec<ObjectiveDef>,
+    /// width and height of the playable area, never larger than the map
+    pub bounds: Vec2,
+}
+
+impl Default for MapData {
+    fn default() -> Self {
+        Self {
+            terrain: TerrainGrid::default(),
+            obstacles: Vec::new(),
+            pickups: Vec::new(),
+            spawns: Vec::new(),
+            objectives: Vec::new(),
+            bounds: BOUNDS.min(Vec2::splat(MAP_SIZE as f32)),
+        }
+    }
+}
+
+impl MapData {
+    /// The hand made arena baked into the binary
+    pub fn arena() -> Self {
+        Self::parse(DEFAULT_MAP).expect("failed to parse map")
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut map = MapData::default();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let directive = words.next().unwrap_or_default();
+            let args: Vec<&str> = words.collect();
+            let error = |message: &str| format!("line {}: {message}: `{line}`", number + 1);
+
+            match directive {
+                "terrain" => {
+                    let [kind, x, y, width, height] = args[..] else {
+                        return Err(error("expected `terrain <type> <x> <y> <width> <height>`"));
+                    };
+                    let terrain = TerrainType::from_name(kind).ok_or_else(|| error("unknown terrain"))?;
+                    let [x, y, width, height] = parse_numbers([x, y, width, height])
+                        .ok_or_else(|| error("expected whole tile coordinates"))?;
+                    map.terrain.fill(x, y, width, height, terrain);
+                }
+                "obstacle" => {
+                    let (kind, numbers, health) = match args[..] {
+                        [kind, x, y, width, height] => (kind, [x, y, width, height], None),
+                        [kind, x, y, width, height, health] => (kind, [x, y, width, height], Some(health)),
+                        _ => return Err(error("expected `obstacle <kind> <x> <y> <width> <height> [health]`")),
+                    };
+                    let kind = ObstacleKind::from_name(kind).ok_or_else(|| error("unknown obstacle"))?;
+                    let [x, y, width, height] = parse_numbers(numbers)
+                        .ok_or_else(|| error("expected numbers for position and size"))?;
+                    let health = match health {
+                        Some(health) => Some(health.parse().map_err(|_| error("expected a number for health"))?),
+                        None => None,
+                    };
+                    map.obstacles.push(ObstacleDef {
+                        kind,
+                        center: Vec2::new(x, y),
+                        size: Vec2::new(width, height),
+                        health,
+                    });
+                }
+                "pickup" => {
+                    let (kind, numbers, respawn_time) = match args[..] {
+                        [kind, x, y] => (kind, [x, y], None),
+                        [kind, x, y, respawn_time] => (kind, [x, y], Some(respawn_time)),
+                        _ => return Err(error("expected `pickup <kind> <x> <y> [respawn seconds]`")),
+                    };
+                    let kind = PickupKind::from_name(kind).ok_or_else(|| error("unknown pickup"))?;
+                    let [x, y] = parse_numbers(numbers).ok_or_else(|| error("expected numbers for position"))?;
+                    let respawn_time = match respawn_time {
+                        Some(respawn_time) => respawn_time
+                            .parse()
+                            .map_err(|_| error("expected a number for respawn seconds"))?,
+                        None => DEFAULT_RESPAWN_TIME,
+                    };
+                    map.pickups.push(PickupDef {
+                        kind,
+                        center: Vec2::new(x, y),
+                        respawn_time,
+                    });
+                }
+                "spawn" => {
+                    let (numbers, team) = match args[..] {
+                        [x, y] => ([x, y], None),
+                        [x, y, team] => ([x, y], Some(team)),
+                        _ => return Err(error("expected `spawn <x> <y> [team]`")),
+                    };
+                    let [x, y] = parse_numbers(numbers).ok_or_else(|| error("expected numbers for position"))?;
+                    let team = match team {
+                        Some(team) => Some(team.parse().map_err(|_| error("expected a whole number for team"))?),
+                        None => None,
+                    };
+                    map.spawns.push(SpawnDef {
+                        center: Vec2::new(x, y),
+                        team,
+                    });
+                }
+                "objective" => {
+                    let (numbers, radius) = match args[..] {
+                        [x, y] => ([x, y], None),
+                        [x, y, radius] => ([x, y], Some(radius)),
+                        _ => return Err(error("expected `objective <x> <y> [radius]`")),
+                    };
+                    let [x, y] = parse_numbers(numbers).ok_or_else(|| error("expected numbers for position"))?;
+                    let radius = match radius {
+                        Some(radius) => radius.parse().map_err(|_| error("expected a number for radius"))?,
+                        None => DEFAULT_OBJECTIVE_RADIUS,
+                    };
+                    map.objectives.push(ObjectiveDef {
+                        center: Vec2::new(x, y),
+                        radius,
+                    });
+                }
+                "bounds" => {
+                    let [width, height] = args[..] else {
+                        return Err(error("expected `bounds <width> <height>`"));
+                    };
+                    let [width, height]: [f32; 2] = parse_numbers([width, height])
+                        .ok_or_else(|| error("expected numbers for width and height"))?;
+                    map.bounds = Vec2::new(width, height).clamp(Vec2::ZERO, Vec2::splat(MAP_SIZE as f32));
+                }
+                _ => return Err(error("unknown directive")),
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Writes the map back out in the map file format, parsing the result gives the same map
+    pub fn to_source(&self) -> String {
+        let mut source = String::from("# Tanky Bois map\n\n");
+        source += &format!("bounds {} {}\n", self.bounds.x, self.bounds.y);
+
+        for (x, y, length, terrain) in self.terrain.runs() {
+            source += &format!("terrain {} {x} {y} {length} 1\n", terrain.name());
+        }
+        for obstacle in &self.obstacles {
+            let ObstacleDef { kind, center, size, health } = obstacle;
+            source += &format!("obstacle {} {} {} {} {}", kind.name(), center.x, center.y, size.x, size.y);
+            if let Some(health) = health {
+                source += &format!(" {health}");
+            }
+            source.push('\n');
+        }
+        for pickup in &self.pickups {
+            source += &format!("pickup {} {} {}", pickup.kind.name(), pickup.center.x, pickup.center.y);
+            if pickup.respawn_time != DEFAULT_RESPAWN_TIME {
+                source += &format!(" {}", pickup.respawn_time);
+            }
+            source.push('\n');
+        }
+        for spawn in &self.spawns {
+            source += &format!("spawn {} {}", spawn.center.x, spawn.center.y);
+            if let Some(team) = spawn.team {
+                source += &format!(" {team}");
+            }
+            source.push('\n');
+        }
+        for objective in &self.objectives {
+            source += &format!("objective {} {}", objective.center.x, objective.center.y);
+            if objective.radius != DEFAULT_OBJECTIVE_RADIUS {
+                source += &format!(" {}", objective.radius);
+            }
+            source.push('\n');
+        }
+
+        source
+    }
+
+    /// The walls closing off the playable area, just outside its edges
+    pub fn boundary_walls(&self) -> [ObstacleDef; 4] {
+        let half = self.bounds / 2.;
+        let wall = |center: Vec2, size: Vec2| ObstacleDef {
+            kind: ObstacleKind::Wall,
+            center,
+            size,
+            health: None,
+        };
+        // the walls overlap at the corners so there is no gap to squeeze through
+        let across = Vec2::new(self.bounds.x + 2. * BOUNDARY_THICKNESS, BOUNDARY_THICKNESS);
+        let along = Vec2::new(BOUNDARY_THICKNESS, self.bounds.y + 2. * BOUNDARY_THICKNESS);
+        let offset = BOUNDARY_THICKNESS / 2.;
+        [
+            wall(Vec2::new(0., half.y + offset), across),
+            wall(Vec2::new(0., -half.y - offset), across),
+            wall(Vec2::new(half.x + offset, 0.), along),
+            wall(Vec2::new(-half.x - offset, 0.), along),
+        ]
+    }
+
+    /// Hash of the map's source, peers with the same custom map come up with the same fingerprint
+    ///
+    /// FNV-1a rather than a std hasher, whose output isn't guaranteed to match between builds.
+    pub fn fingerprint(&self) -> u64 {
+        self.to_source()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+    }
+
+    /// Reads a map file from disk
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        Self::parse(&source).map_err(|err| format!("{path}: {err}"))
+    }
+}
+
+/// Parses a fixed number of map file arguments
+fn parse_numbers<T: std::str::FromStr, const N: usize>(args: [&str; N]) -> Option<[T; N]> {
+    let parsed: Vec<T> = args.iter().map(|arg| arg.parse().ok()).collect::<Option<_>>()?;
+    parsed.try_into().ok()
+}
+
+/// Which map the next match is played on
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MyMapChoice {
+    /// the hand made arena baked into the binary
+    #[default]
+    Arena,
+    /// a fresh layout generated from the seed the peers agreed on
+    Generated,
+    /// the map last saved from the editor, peers are only matched with others who have the same map
+    Custom,
+}
+
+/// The custom map this match is played on, read from disk before connecting so peers can be matched by its
+/// fingerprint and everyone plays the map they were matched on
+#[derive(Resource)]
+pub struct CustomMap(pub MapData);
+
+impl MyMapChoice {
+    pub fn name(self) -> &'static str {
+        match self {
+            MyMapChoice::Arena => "Arena",
+            MyMapChoice::Generated => "Random",
+            MyMapChoice::Custom => "Custom",
+        }
+    }
+}
+
+/// Loads or generates the map so the rest of the world can be built from it
+pub fn load_map(
+    mut commands: Commands,
+    choice: Res<MyMapChoice>,
+    seed: Res<MapSeed>,
+    params: Res<MapGenParams>,
+    custom: Option<Res<CustomMap>>,
+) {
+    let map = match *choice {
+        MyMapChoice::Arena => MapData::arena(),
+        MyMapChoice::Generated => {
+            info!("generating map from seed {:#x}", seed.0);
+            mapgen::generate(seed.0, &params)
+        }
+        // the peers were matched on this map, anything else would desync
+        MyMapChoice::Custom => custom.expect("custom map wasn't loaded before connecting").0.clone(),
+    };
+    commands.insert_resource(map);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One of every directive, with every optional argument
+    const EVERYTHING: &str = "
+        bounds 300 200.5
+        terrain mud 10 20 3 2
+        terrain ice 0 0 1 1
+        obstacle wall 1.5 -2.25 10 1.5
+        obstacle building -40 30 8 6 250
+        pickup repair 5 5
+        pickup speed -5 5 12.5
+        spawn -100 0
+        spawn 100 0 1
+        objective 0 0
+        objective 50 -20 15.5
+    ";
+
+    /// Parses a map, writes it back out and parses that again
+    fn round_trip(source: &str) -> (MapData, MapData) {
+        let first = MapData::parse(source).expect("failed to parse map");
+        let second = MapData::parse(&first.to_source()).expect("failed to parse written map");
+        (first, second)
+    }
+
+    #[test]
+    fn arena_round_trips() {
+        let (first, second) = round_trip(DEFAULT_MAP);
+        assert_eq!(first.to_source(), second.to_source());
+        assert_eq!(first.obstacles.len(), second.obstacles.len());
+        assert_eq!(first.fingerprint(), second.fingerprint());
+    }
+
+    #[test]
+    fn every_directive_round_trips() {
+        let (first, second) = round_trip(EVERYTHING);
+        for map in [&first, &second] {
+            assert_eq!(map.bounds, Vec2::new(300., 200.5));
+            assert_eq!(map.terrain.at(TerrainGrid::tile_center(11, 21)), TerrainType::Mud);
+            assert_eq!(map.terrain.at(TerrainGrid::tile_center(0, 0)), TerrainType::Ice);
+            assert_eq!(map.terrain.at(TerrainGrid::tile_center(13, 21)), TerrainType::default());
+
+            let [wall, building] = &map.obstacles[..] else {
+                panic!("expected two obstacles");
+            };
+            assert_eq!((wall.center, wall.size, wall.health), (Vec2::new(1.5, -2.25), Vec2::new(10., 1.5), None));
+            assert_eq!(building.health, Some(250.));
+
+            let [repair, speed] = &map.pickups[..] else {
+                panic!("expected two pickups");
+            };
+            assert_eq!(repair.respawn_time, DEFAULT_RESPAWN_TIME);
+            assert_eq!((speed.center, speed.respawn_time), (Vec2::new(-5., 5.), 12.5));
+
+            let teams: Vec<Option<usize>> = map.spawns.iter().map(|spawn| spawn.team).collect();
+            assert_eq!(teams, [None, Some(1)]);
+
+            let [middle, flank] = &map.objectives[..] else {
+                panic!("expected two objectives");
+            };
+            assert_eq!((middle.center, middle.radius), (Vec2::ZERO, DEFAULT_OBJECTIVE_RADIUS));
+            assert_eq!((flank.center, flank.radius), (Vec2::new(50., -20.), 15.5));
+        }
+        assert_eq!(first.to_source(), second.to_source());
+    }
+
+    #[test]
+    fn generated_map_round_trips() {
+        let (first, second) = round_trip(&mapgen::generate(3, &MapGenParams::default()).to_source());
+        assert_eq!(first.to_source(), second.to_source());
+    }
+
+    #[test]
+    fn fingerprint_tells_maps_apart() {
+        let map = MapData::parse(EVERYTHING).unwrap();
+        let mut moved = map.clone();
+        moved.obstacles[0].center.x += 0.5;
+        assert_eq!(map.fingerprint(), MapData::parse(EVERYTHING).unwrap().fingerprint());
+        assert_ne!(map.fingerprint(), moved.fingerprint());
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let err = MapData::parse("bounds 10 10\nobstacle tree 0 0 1 1").err().expect("unknown obstacle parsed");
+        assert!(err.starts_with("line 2:"), "{err}");
+        assert!(MapData::parse("spawn 1").is_err());
+        assert!(MapData::parse("fly 1 2").is_err());
+    }
+
+    #[test]
+    fn bounds_are_clamped_to_the_map() {
+        let map = MapData::parse("bounds 5000 -10").unwrap();
+        assert_eq!(map.bounds, Vec2::new(MAP_SIZE as f32, 0.));
+    }
+
+    #[test]
+    fn walls_close_off_the_playable_area() {
+        let map = MapData::parse("bounds 300 200").unwrap();
+        let walls = map.boundary_walls();
+        let half = map.bounds / 2.;
+
+        // each wall's inner edge lies on the boundary
+        let inner_edges: Vec<f32> = walls
+            .iter()
+            .map(|wall| {
+                let edge = wall.center.abs() - wall.size / 2.;
+                if wall.center.x == 0. { edge.y } else { edge.x }
+            })
+            .collect();
+        assert_eq!(inner_edges, [half.y, half.y, half.x, half.x]);
+
+        // and the walls reach past each other so the corners are closed
+        for wall in &walls {
+            let reach = wall.size / 2.;
+            if wall.center.x == 0. {
+                assert!(reach.x >= half.x + BOUNDARY_THICKNESS);
+            } else {
+                assert!(reach.y >= half.y + BOUNDARY_THICKNESS);
+            }
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use bevy::prelude::*;
+
+use crate::map::MapData;
+use crate::obstacle::{ObstacleDef, ObstacleKind};
+use crate::pickup::{PickupDef, DEFAULT_RESPAWN_TIME, PICKUP_KINDS};
+use crate::rng::RollbackRng;
+use crate::spawn::SpawnDef;
+use crate::terrain::{TerrainType, TILES_PER_SIDE, TILE_SIZE};
+use crate::MAP_SIZE;
+
+/// Open ground between the generated layout and the walls around it in meters
+const BOUNDARY_MARGIN: f32 = 30.;
+/// Room left between generated obstacles so a hull always fits through, in meters
+const OBSTACLE_GAP: f32 = 4.;
+/// How far generated obstacles keep from spawn points and pickups in meters
+const SPAWN_CLEARANCE: f32 = 12.;
+/// Attempts at placing each obstacle or pickup before giving up on it
+const PLACEMENT_ATTEMPTS: usize = 20;
+/// Width of a generated road in tiles
+const ROAD_WIDTH: usize = 4;
+/// Slow or slippery ground a generated terrain patch can be made of
+const PATCH_TERRAIN: [TerrainType; 4] = [TerrainType::Mud, TerrainType::Sand, TerrainType::Water, TerrainType::Ice];
+
+/// The seed every peer generates the map from, agreed on before the session starts
+#[derive(Resource, Clone, Copy, Default, Debug)]
+pub struct MapSeed(pub u64);
+
+impl MapSeed {
+    /// Derives the seed from the ids of everyone in the match, which every peer knows once they have all joined
+    ///
+    /// The ids are folded through our own random number generator rather than a std hasher, whose output can change
+    /// between Rust releases and between 32 and 64 bit builds, and peers built differently still have to agree.
+    pub fn from_peers(mut peers: Vec<u128>) -> Self {
+        // sorted so the seed doesn't depend on the order each peer sees the others in
+        peers.sort_unstable();
+        let seed = peers
+            .into_iter()
+            .flat_map(|peer| [(peer >> 64) as u64, peer as u64])
+            .fold(0, |seed, half| RollbackRng::with_seed(seed ^ half).next_u64());
+        Self(seed)
+    }
+}
+
+/// What a generated map is made of, every peer has to use the same parameters to end up with the same map
+#[derive(Resource, Clone, Debug)]
+pub struct MapGenParams {
+    /// half the width and height of the square around the centre the layout is spread over, in meters
+    pub half_size: f32,
+    /// patches of slow or slippery ground
+    pub terrain_patches: usize,
+    pub obstacles: usize,
+    /// chance that a generated obstacle can be destroyed
+    pub destructible_chance: f32,
+    pub pickups: usize,
+    pub spawns: usize,
+}
+
+impl Default for MapGenParams {
+    fn default() -> Self {
+        Self {
+            half_size: 120.,
+            terrain_patches: 8,
+            obstacles: 24,
+            destructible_chance: 0.4,
+            pickups: 6,
+            spawns: 8,
+        }
+    }
+}
+
+/// Generates an arena layout from a seed
+///
+/// Everything is placed in pairs mirrored through the centre of the map, so neither side of the arena is better than
+/// the other. Obstacles keep clear of each other, the spawn points and the pickups so every spot can be driven to.
+pub fn generate(seed: u64, params: &MapGenParams) -> MapData {
+    let mut rng = RollbackRng::with_seed(seed);
+    let mut map = MapData::default();
+    let half_size = params.half_size;
+    map.bounds = Vec2::splat(2. * (half_size + BOUNDARY_MARGIN)).min(map.bounds);
+
+    // a pair of roads each way across the map, anywhere from running together through the middle to well apart
+    let offset = (rng.range(0., 0.4) * half_size / TILE_SIZE) as usize;
+    let road = TILES_PER_SIDE / 2 - ROAD_WIDTH / 2 - offset;
+    for road in [road, TILES_PER_SIDE - road - ROAD_WIDTH] {
+        map.terrain.fill(0, road, TILES_PER_SIDE, ROAD_WIDTH, TerrainType::Road);
+        map.terrain.fill(road, 0, ROAD_WIDTH, TILES_PER_SIDE, TerrainType::Road);
+    }
+
+    for _ in 0..params.terrain_patches.div_ceil(2) {
+        let terrain = PATCH_TERRAIN[(rng.next_u64() % PATCH_TERRAIN.len() as u64) as usize];
+        let size = Vec2::new(rng.range(4., 20.), rng.range(4., 20.)).floor();
+        let center = Vec2::new(rng.range(-half_size, half_size), rng.range(-half_size, half_size));
+        let corner = ((center + MAP_SIZE as f32 / 2.) / TILE_SIZE - size / 2.).max(Vec2::ZERO).floor();
+        let (x, y) = (corner.x as usize, corner.y as usize);
+        let (width, height) = (size.x as usize, size.y as usize);
+        map.terrain.fill(x, y, width, height, terrain);
+        let (mirror_x, mirror_y) = (
+            TILES_PER_SIDE.saturating_sub(x + width),
+            TILES_PER_SIDE.saturating_sub(y + height),
+        );
+        map.terrain.fill(mirror_x, mirror_y, width, height, terrain);
+    }
+
+    // spawn points around a ring, evenly spaced so everyone starts the same distance from the middle
+    let start_angle = rng.range(0., std::f32::consts::TAU);
+    for index in 0..params.spawns {
+        let angle = start_angle + std::f32::consts::TAU * index as f32 / params.spawns as f32;
+        map.spawns.push(SpawnDef {
+            center: Vec2::from_angle(angle) * half_size * 0.85,
+            team: None,
+        });
+    }
+
+    let limit = half_size - SPAWN_CLEARANCE;
+    let mut placed = 0;
+    while placed < params.obstacles {
+        let kind = match rng.next_u64() % 3 {
+            0 => ObstacleKind::Wall,
+            1 => ObstacleKind::Rock,
+            _ => ObstacleKind::Building,
+        };
+        let size = match kind {
+            ObstacleKind::Wall if rng.next_f32() < 0.5 => Vec2::new(rng.range(8., 30.), 1.5),
+            ObstacleKind::Wall => Vec2::new(1.5, rng.range(8., 30.)),
+            ObstacleKind::Rock => Vec2::new(rng.range(3., 7.), rng.range(3., 7.)),
+            ObstacleKind::Building => Vec2::new(rng.range(6., 12.), rng.range(6., 10.)),
+        };
+        let health = (rng.next_f32() < params.destructible_chance).then(|| (size.x * size.y * 10.).clamp(100., 500.));
+
+        let Some(center) = (0..PLACEMENT_ATTEMPTS)
+            .map(|_| Vec2::new(rng.range(-limit, limit), rng.range(-limit, limit)))
+            .find(|&center| {
+                // the mirrored copy has to fit too, and can't overlap the original
+                let fits = |center: Vec2| {
+                    map.spawns.iter().all(|spawn| rect_distance(center, size, spawn.center) > SPAWN_CLEARANCE)
+                        && map.obstacles.iter().all(|obstacle| {
+                            rects_apart(center, size, obstacle.center, obstacle.size, OBSTACLE_GAP)
+                        })
+                };
+                fits(center) && fits(-center) && rects_apart(center, size, -center, size, OBSTACLE_GAP)
+            })
+        else {
+            // crowded maps just get fewer obstacles
+            break;
+        };
+
+        for center in [center, -center] {
+            map.obstacles.push(ObstacleDef {
+                kind,
+                center,
+                size,
+                health,
+            });
+        }
+        placed += 2;
+    }
+
+    let mut pickups = 0;
+    while pickups < params.pickups {
+        let kind = PICKUP_KINDS[(pickups / 2) % PICKUP_KINDS.len()];
+        let Some(center) = (0..PLACEMENT_ATTEMPTS)
+            .map(|_| Vec2::new(rng.range(-limit, limit), rng.range(-limit, limit)))
+            .find(|&center| {
+                map.obstacles
+                    .iter()
+                    .all(|obstacle| rect_distance(obstacle.center, obstacle.size, center) > OBSTACLE_GAP)
+                    && map.pickups.iter().all(|pickup| pickup.center.distance(center) > SPAWN_CLEARANCE)
+                    && center.length() > SPAWN_CLEARANCE / 2.
+            })
+        else {
+            break;
+        };
+
+        for center in [center, -center] {
+            map.pickups.push(PickupDef {
+                kind,
+                center,
+                respawn_time: DEFAULT_RESPAWN_TIME,
+            });
+        }
+        pickups += 2;
+    }
+
+    map
+}
+
+/// Distance from a point to the nearest edge of an axis aligned rectangle, zero inside it
+fn rect_distance(center: Vec2, size: Vec2, point: Vec2) -> f32 {
+    ((point - center).abs() - size / 2.).max(Vec2::ZERO).length()
+}
+
+/// Whether two axis aligned rectangles are at least `gap` apart along one of the axes
+fn rects_apart(a: Vec2, a_size: Vec2, b: Vec2, b_size: Vec2, gap: f32) -> bool {
+    let apart = (a - b).abs() - (a_size + b_size) / 2.;
+    apart.x >= gap || apart.y >= gap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_ignores_peer_order() {
+        let a = MapSeed::from_peers(vec![3, 1 << 100, 42]);
+        let b = MapSeed::from_peers(vec![42, 3, 1 << 100]);
+        assert_eq!(a.0, b.0);
+        assert_ne!(a.0, MapSeed::from_peers(vec![3, 42]).0);
+    }
+
+    #[test]
+    fn seed_is_pinned() {
+        // peers built with different toolchains or for different targets have to agree on this
+        assert_eq!(MapSeed::from_peers(vec![2, 1]).0, 0xbedb_5bf1_cd5e_c111);
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_map() {
+        let params = MapGenParams::default();
+        for seed in [0, 1, 0xdead_beef, u64::MAX] {
+            assert_eq!(generate(seed, &params).to_source(), generate(seed, &params).to_source());
+        }
+        assert_ne!(generate(1, &params).to_source(), generate(2, &params).to_source());
+    }
+
+    #[test]
+    fn layout_is_mirrored_through_the_centre() {
+        let map = generate(7, &MapGenParams::default());
+        for obstacle in &map.obstacles {
+            assert!(map
+                .obstacles
+                .iter()
+                .any(|other| other.center == -obstacle.center && other.size == obstacle.size));
+        }
+        for pickup in &map.pickups {
+            assert!(map.pickups.iter().any(|other| other.center == -pickup.center));
+        }
+    }
+
+    #[test]
+    fn obstacles_keep_clear_of_spawns() {
+        let params = MapGenParams::default();
+        for seed in 0..50 {
+            let map = generate(seed, &params);
+            for obstacle in &map.obstacles {
+                for spawn in &map.spawns {
+                    assert!(
+                        rect_distance(obstacle.center, obstacle.size, spawn.center) > SPAWN_CLEARANCE,
+                        "seed {seed}: obstacle at {} crowds the spawn at {}",
+                        obstacle.center,
+                        spawn.center,
+                    );
+                }
+            }
+        }
+    }
+}
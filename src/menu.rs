@@ -0,0 +1,191 @@
+use bevy::prelude::*;
+
+use crate::map::MyMapChoice;
+use crate::bot::Difficulty;
+use crate::tank_class::TankClass;
+use crate::{AppState, MyBots, MyGameMode, MyNumPlayers, MyTankClass, GAME_MODES, MAX_PLAYERS};
+
+/// Root of the main menu's UI
+#[derive(Component)]
+pub struct MenuScreen;
+
+/// Menu line showing which map is picked
+#[derive(Component)]
+pub struct MapChoiceLabel;
+
+/// Menu line showing how many players a match is for
+#[derive(Component)]
+pub struct NumPlayersLabel;
+
+/// Menu line showing whether bots fill in for the players who don't turn up
+#[derive(Component)]
+pub struct BotsLabel;
+
+/// Menu line showing which tank class the local player takes into the match
+#[derive(Component)]
+pub struct TankClassLabel;
+
+fn map_choice_text(choice: MyMapChoice) -> String {
+    format!("M  Map: {}", choice.name())
+}
+
+fn tank_class_text(class: TankClass) -> String {
+    format!("C  Tank: {}", class.name())
+}
+
+fn num_players_text(num_players: u16) -> String {
+    format!("P  Players: {num_players}")
+}
+
+fn bots_text(bots: Option<Difficulty>) -> String {
+    match bots {
+        Some(difficulty) => format!("B  Bots: {difficulty:?}"),
+        None => "B  Bots: Off".to_string(),
+    }
+}
+
+/// Lists the game modes, one number key each, the map choice, the tank class, the player count and the bot setting
+pub fn spawn_menu(
+    mut commands: Commands,
+    map_choice: Res<MyMapChoice>,
+    my_tank_class: Res<MyTankClass>,
+    my_num_players: Res<MyNumPlayers>,
+    my_bots: Res<MyBots>,
+) {
+    let style = |font_size: f32| TextStyle {
+        font_size,
+        color: Color::WHITE,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(12.),
+                    ..default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.7).into(),
+                ..default()
+            },
+            MenuScreen,
+        ))
+        .with_children(|menu| {
+            menu.spawn(TextBundle::from_section("Tanky Bois", style(64.)));
+            for (index, mode) in GAME_MODES.iter().enumerate() {
+                menu.spawn(TextBundle::from_section(format!("{}  {}", index + 1, mode.name()), style(32.)));
+            }
+            menu.spawn((TextBundle::from_section(map_choice_text(*map_choice), style(24.)), MapChoiceLabel));
+            menu.spawn((TextBundle::from_section(tank_class_text(my_tank_class.0), style(24.)), TankClassLabel));
+            menu.spawn((TextBundle::from_section(num_players_text(my_num_players.0), style(24.)), NumPlayersLabel));
+            menu.spawn((TextBundle::from_section(bots_text(my_bots.0), style(24.)), BotsLabel));
+            menu.spawn(TextBundle::from_section("E  Map editor", style(24.)));
+        });
+}
+
+/// Starts the game mode picked with the number keys, or opens the map editor with E
+pub fn choose_game_mode(
+    keys: Res<Input<KeyCode>>,
+    mut game_mode: ResMut<MyGameMode>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let mode_keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+    for (key, mode) in mode_keys.into_iter().zip(GAME_MODES) {
+        if keys.just_pressed(key) {
+            *game_mode = mode;
+            next_state.set(AppState::Connecting);
+            info!("game mode: {:?}", mode);
+        }
+    }
+    if keys.just_pressed(KeyCode::E) {
+        next_state.set(AppState::Editor);
+    }
+}
+
+/// Cycles between the arena, a generated map and the map saved from the editor with M
+pub fn toggle_map_choice(
+    keys: Res<Input<KeyCode>>,
+    mut map_choice: ResMut<MyMapChoice>,
+    mut label_query: Query<&mut Text, With<MapChoiceLabel>>,
+) {
+    if !keys.just_pressed(KeyCode::M) {
+        return;
+    }
+
+    *map_choice = match *map_choice {
+        MyMapChoice::Arena => MyMapChoice::Generated,
+        MyMapChoice::Generated => MyMapChoice::Custom,
+        MyMapChoice::Custom => MyMapChoice::Arena,
+    };
+    for mut text in &mut label_query {
+        text.sections[0].value = map_choice_text(*map_choice);
+    }
+}
+
+/// Cycles through the tank classes with C, the other peers are told the pick once they join
+pub fn cycle_tank_class(
+    keys: Res<Input<KeyCode>>,
+    mut my_tank_class: ResMut<MyTankClass>,
+    mut label_query: Query<&mut Text, With<TankClassLabel>>,
+) {
+    if !keys.just_pressed(KeyCode::C) {
+        return;
+    }
+
+    my_tank_class.0 = my_tank_class.0.next();
+    for mut text in &mut label_query {
+        text.sections[0].value = tank_class_text(my_tank_class.0);
+    }
+}
+
+/// Cycles the number of players a match is for with P, from playing alone up to a full room
+pub fn cycle_num_players(
+    keys: Res<Input<KeyCode>>,
+    mut my_num_players: ResMut<MyNumPlayers>,
+    mut label_query: Query<&mut Text, With<NumPlayersLabel>>,
+) {
+    if !keys.just_pressed(KeyCode::P) {
+        return;
+    }
+
+    my_num_players.0 = my_num_players.0 % MAX_PLAYERS + 1;
+    for mut text in &mut label_query {
+        text.sections[0].value = num_players_text(my_num_players.0);
+    }
+}
+
+/// Cycles the bots between off and each difficulty with B, with bots on a match only waits a while for the room to fill
+/// up before bots take the other players' handles
+pub fn toggle_bots(
+    keys: Res<Input<KeyCode>>,
+    mut my_bots: ResMut<MyBots>,
+    mut label_query: Query<&mut Text, With<BotsLabel>>,
+) {
+    if !keys.just_pressed(KeyCode::B) {
+        return;
+    }
+
+    my_bots.0 = match my_bots.0 {
+        None => Some(Difficulty::Easy),
+        Some(Difficulty::Easy) => Some(Difficulty::Normal),
+        Some(Difficulty::Normal) => Some(Difficulty::Hard),
+        Some(Difficulty::Hard) => None,
+    };
+    for mut text in &mut label_query {
+        text.sections[0].value = bots_text(my_bots.0);
+    }
+}
+
+pub fn despawn_menu(
+    mut commands: Commands,
+    menu_query: Query<Entity, With<MenuScreen>>,
+) {
+    for entity in &menu_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
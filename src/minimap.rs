@@ -0,0 +1,386 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_ggrs::*;
+
+use crate::fog::FogOfWar;
+use crate::health::Health;
+use crate::map::MapData;
+use crate::obstacle::{is_standing, Destructible, Obstacle};
+use crate::{Player, TankInputs, MAP_SIZE};
+
+/// Input bit for dropping a ping, the ping's position is packed into the bits above it
+pub const INPUT_PING: u64 = 1 << 14;
+/// Bits of the input holding the ping position, one byte per axis across the whole map
+const INPUT_PING_X_SHIFT: u64 = 16;
+const INPUT_PING_Y_SHIFT: u64 = 24;
+
+/// Width of the minimap on screen in pixels, the height follows the shape of the playable area
+const MINIMAP_WIDTH: f32 = 220.;
+/// Width and height of a tank marker in pixels
+const MARKER_SIZE: f32 = 6.;
+/// Width and height of a ping marker in pixels
+const PING_MARKER_SIZE: f32 = 10.;
+/// Seconds a ping stays up
+const PING_DURATION: f32 = 5.;
+/// Radius of the ring a ping draws in the world in meters
+const PING_RADIUS: f32 = 4.;
+
+/// Packs a ping position into the input
+pub fn encode_ping(position: Vec2) -> u64 {
+    let quantize = |value: f32| ((value / MAP_SIZE as f32 + 0.5) * 255.).round().clamp(0., 255.) as u64;
+    INPUT_PING | (quantize(position.x) << INPUT_PING_X_SHIFT) | (quantize(position.y) << INPUT_PING_Y_SHIFT)
+}
+
+/// Unpacks the ping position from an input, `None` when the input doesn't ping
+fn decode_ping(input: u64) -> Option<Vec2> {
+    if input & INPUT_PING == 0 {
+        return None;
+    }
+    let unquantize = |shift: u64| ((input >> shift) & 0xff) as f32 / 255. * MAP_SIZE as f32 - MAP_SIZE as f32 / 2.;
+    Some(Vec2::new(unquantize(INPUT_PING_X_SHIFT), unquantize(INPUT_PING_Y_SHIFT)))
+}
+
+/// A marked spot on the map
+#[derive(Clone)]
+pub struct Ping {
+    /// handle of the player who placed it, only their team gets to see it
+    pub owner: usize,
+    pub position: Vec2,
+    /// seconds until it goes away
+    pub remaining: f32,
+}
+
+/// Every ping that is up, rolled back so every peer agrees on them
+#[derive(Resource, Clone, Default)]
+pub struct Pings(pub Vec<Ping>);
+
+/// Places the pings players ask for, one per player at a time, and takes down the ones that have run out
+pub fn update_pings(
+    inputs: Res<TankInputs>,
+    time: Res<Time>,
+    mut pings: ResMut<Pings>,
+    player_query: Query<&Player>,
+) {
+    for ping in &mut pings.0 {
+        ping.remaining -= time.delta_seconds();
+    }
+    pings.0.retain(|ping| ping.remaining > 0.);
+
+    // in handle order so the pings line up the same way on every peer
+    let mut handles: Vec<usize> = player_query.iter().map(|ship| ship.handle).collect();
+    handles.sort_unstable();
+    for handle in handles {
+        let Some(position) = decode_ping(inputs[handle]) else {
+            continue;
+        };
+        pings.0.retain(|ping| ping.owner != handle);
+        pings.0.push(Ping {
+            owner: handle,
+            position,
+            remaining: PING_DURATION,
+        });
+    }
+}
+
+/// The minimap panel, covering the playable area of the map
+#[derive(Component)]
+pub struct Minimap {
+    bounds: Vec2,
+}
+
+/// A tank's marker on the minimap
+#[derive(Component)]
+pub struct MinimapMarker {
+    handle: usize,
+}
+
+/// An obstacle's outline on the minimap
+#[derive(Component)]
+pub struct MinimapObstacle {
+    obstacle: Entity,
+}
+
+/// A ping's marker on the minimap
+#[derive(Component)]
+pub struct MinimapPing {
+    owner: usize,
+}
+
+/// Where the local player clicked on the minimap, waiting to go out with the next input
+#[derive(Resource, Default)]
+pub struct MinimapInput {
+    pub ping: Option<Vec2>,
+    /// whether the cursor is over the minimap, clicks there don't fire
+    pub hovered: bool,
+}
+
+/// Spot on the minimap of a world position, as percentages from the bottom left corner
+fn minimap_position(bounds: Vec2, position: Vec2) -> Vec2 {
+    ((position / bounds + 0.5) * 100.).clamp(Vec2::ZERO, Vec2::splat(100.))
+}
+
+/// An absolutely placed square marker centred on a spot on the minimap
+fn marker_style(spot: Vec2, size: f32) -> Style {
+    Style {
+        position_type: PositionType::Absolute,
+        left: Val::Percent(spot.x),
+        bottom: Val::Percent(spot.y),
+        width: Val::Px(size),
+        height: Val::Px(size),
+        margin: UiRect {
+            left: Val::Px(-size / 2.),
+            bottom: Val::Px(-size / 2.),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+/// Draws the outline of the map in the top left corner, the obstacles and markers are added onto it as they show up
+pub fn spawn_minimap(mut commands: Commands, map: Res<MapData>) {
+    let bounds = map.bounds;
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.),
+                left: Val::Px(10.),
+                width: Val::Px(MINIMAP_WIDTH),
+                height: Val::Px(MINIMAP_WIDTH * bounds.y / bounds.x),
+                border: UiRect::all(Val::Px(2.)),
+                ..default()
+            },
+            background_color: Color::rgba(0.1, 0.1, 0.1, 0.7).into(),
+            border_color: Color::WHITE.into(),
+            ..default()
+        },
+        Minimap { bounds },
+    ));
+}
+
+type ObstacleChangeQuery<'w, 's> = Query<'w, 's, (), Or<(Added<Obstacle>, Changed<Destructible>)>>;
+
+/// Keeps the obstacles on the minimap in step with the ones standing in the world
+///
+/// Destroyed obstacles drop off the map and placed barricades show up on it. Obstacles never move, so the nodes are
+/// only touched when one is added, destroyed, rebuilt by a rollback or removed.
+pub fn update_minimap_obstacles(
+    mut commands: Commands,
+    minimap_query: Query<(Entity, &Minimap)>,
+    obstacle_query: Query<(Entity, &Obstacle, &Transform, &Sprite, Option<&Destructible>)>,
+    changed_query: ObstacleChangeQuery,
+    mut removed: RemovedComponents<Obstacle>,
+    node_query: Query<(Entity, &MinimapObstacle)>,
+) {
+    let removed = removed.read().count() > 0;
+    let Ok((minimap_entity, minimap)) = minimap_query.get_single() else {
+        return;
+    };
+    if changed_query.is_empty() && !removed {
+        return;
+    }
+
+    for (node, minimap_obstacle) in &node_query {
+        let standing = obstacle_query
+            .get(minimap_obstacle.obstacle)
+            .is_ok_and(|(.., destructible)| is_standing(destructible));
+        if !standing {
+            commands.entity(node).despawn_recursive();
+        }
+    }
+
+    for (entity, obstacle, transform, sprite, destructible) in &obstacle_query {
+        if !is_standing(destructible) || node_query.iter().any(|(_, node)| node.obstacle == entity) {
+            continue;
+        }
+
+        // the walls around the map lie just outside the playable area the minimap covers
+        let half_bounds = minimap.bounds / 2.;
+        let center = transform.translation.xy();
+        let min = (center - obstacle.half_extents).max(-half_bounds);
+        let max = (center + obstacle.half_extents).min(half_bounds);
+        if min.cmpge(max).any() {
+            continue;
+        }
+
+        let corner = minimap_position(minimap.bounds, min);
+        let size = (max - min) / minimap.bounds * 100.;
+        let node = commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Percent(corner.x),
+                        bottom: Val::Percent(corner.y),
+                        width: Val::Percent(size.x),
+                        height: Val::Percent(size.y),
+                        min_width: Val::Px(1.),
+                        min_height: Val::Px(1.),
+                        ..default()
+                    },
+                    background_color: sprite.color.with_a(1.).into(),
+                    ..default()
+                },
+                MinimapObstacle { obstacle: entity },
+            ))
+            .id();
+        commands.entity(minimap_entity).add_child(node);
+    }
+}
+
+/// Moves the tank markers, showing the local team's tanks and the enemies it can see
+pub fn update_minimap(
+    mut commands: Commands,
+    local_players: Option<Res<LocalPlayers>>,
+    fog: Res<FogOfWar>,
+    minimap_query: Query<(Entity, &Minimap)>,
+    player_query: Query<(&Player, &Transform, &Health)>,
+    mut marker_query: Query<(Entity, &MinimapMarker, &mut Style, &mut BackgroundColor, &mut Visibility)>,
+) {
+    let Ok((minimap_entity, minimap)) = minimap_query.get_single() else {
+        return;
+    };
+    let local_handles = local_players.map(|local_players| local_players.0.clone()).unwrap_or_default();
+
+    for (ship, transform, health) in &player_query {
+        let Some((_, _, mut style, mut color, mut visibility)) =
+            marker_query.iter_mut().find(|(_, marker, ..)| marker.handle == ship.handle)
+        else {
+            let marker = commands
+                .spawn((NodeBundle::default(), MinimapMarker { handle: ship.handle }))
+                .id();
+            commands.entity(minimap_entity).add_child(marker);
+            continue;
+        };
+
+        let shown = health.current > 0. && fog.visible_handles.contains(&ship.handle);
+        visibility.set_if_neq(if shown { Visibility::Inherited } else { Visibility::Hidden });
+
+        *style = marker_style(minimap_position(minimap.bounds, transform.translation.xy()), MARKER_SIZE);
+        *color = if local_handles.contains(&ship.handle) {
+            Color::WHITE
+        } else if fog.friendly_handles.contains(&ship.handle) {
+            Color::rgb(0.3, 0.5, 1.)
+        } else {
+            Color::RED
+        }
+        .into();
+    }
+
+    // markers of tanks that are gone
+    for (entity, marker, ..) in &marker_query {
+        if !player_query.iter().any(|(ship, ..)| ship.handle == marker.handle) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Queues a ping wherever the local player clicks on the minimap
+pub fn click_minimap(
+    buttons: Res<Input<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    minimap_query: Query<(&Minimap, &Node, &GlobalTransform)>,
+    mut minimap_input: ResMut<MinimapInput>,
+) {
+    let cursor = window_query.get_single().ok().and_then(|window| window.cursor_position());
+    let Some((minimap, node, transform)) = minimap_query.iter().next() else {
+        minimap_input.hovered = false;
+        return;
+    };
+
+    // UI positions are measured from the top left of the window, the same as the cursor
+    let size = node.size();
+    let corner = transform.translation().xy() - size / 2.;
+    let fraction = cursor.map(|cursor| (cursor - corner) / size);
+    let inside = fraction.filter(|fraction| fraction.cmpge(Vec2::ZERO).all() && fraction.cmple(Vec2::ONE).all());
+
+    minimap_input.hovered = inside.is_some();
+    if let (Some(fraction), true) = (inside, buttons.just_pressed(MouseButton::Left)) {
+        let position = (Vec2::new(fraction.x, 1. - fraction.y) - 0.5) * minimap.bounds;
+        minimap_input.ping = Some(position);
+    }
+}
+
+/// Shows the local team's pings on the minimap and as rings in the world
+pub fn draw_pings(
+    mut commands: Commands,
+    pings: Res<Pings>,
+    fog: Res<FogOfWar>,
+    minimap_query: Query<(Entity, &Minimap)>,
+    mut ping_marker_query: Query<(Entity, &MinimapPing, &mut Style, &mut BackgroundColor)>,
+    time: Res<Time>,
+    mut gizmos: Gizmos,
+) {
+    let minimap = minimap_query.get_single().ok();
+    let shown = |ping: &&Ping| fog.friendly_handles.contains(&ping.owner);
+    for ping in pings.0.iter().filter(shown) {
+        // the ring pulses so it stands out against everything else drawn on the map
+        let pulse = 1. + 0.3 * (time.elapsed_seconds() * 6.).sin();
+        gizmos.circle_2d(ping.position, PING_RADIUS * pulse, Color::YELLOW);
+        gizmos.circle_2d(ping.position, 0.5, Color::YELLOW);
+
+        let Some((minimap_entity, minimap)) = minimap else {
+            continue;
+        };
+        let style = marker_style(minimap_position(minimap.bounds, ping.position), PING_MARKER_SIZE);
+        let color = Color::YELLOW.with_a(ping.remaining / PING_DURATION).into();
+        if let Some((.., mut marker_style, mut marker_color)) =
+            ping_marker_query.iter_mut().find(|(_, marker, ..)| marker.owner == ping.owner)
+        {
+            *marker_style = style;
+            *marker_color = color;
+        } else {
+            let marker = commands
+                .spawn((
+                    NodeBundle {
+                        style,
+                        background_color: color,
+                        ..default()
+                    },
+                    MinimapPing { owner: ping.owner },
+                ))
+                .id();
+            commands.entity(minimap_entity).add_child(marker);
+        }
+    }
+
+    // markers of pings that ran out
+    for (entity, marker, ..) in &ping_marker_query {
+        if !pings.0.iter().filter(shown).any(|ping| ping.owner == marker.owner) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Furthest a ping can land from where it was placed along each axis, half a quantisation step and some rounding
+    const PING_ERROR: f32 = MAP_SIZE as f32 / 255. / 2. + 1e-3;
+
+    #[test]
+    fn ping_round_trips_through_the_input() {
+        for position in [Vec2::ZERO, Vec2::new(123.4, -56.7), Vec2::splat(-499.), Vec2::splat(500.)] {
+            let decoded = decode_ping(encode_ping(position)).expect("ping bit not set");
+            assert!((decoded - position).abs().max_element() <= PING_ERROR, "{position} came back as {decoded}");
+        }
+    }
+
+    #[test]
+    fn ping_off_the_map_is_pinned_to_its_edge() {
+        let decoded = decode_ping(encode_ping(Vec2::new(1e6, -1e6))).expect("ping bit not set");
+        assert_eq!(decoded, Vec2::new(MAP_SIZE as f32 / 2., -(MAP_SIZE as f32) / 2.));
+    }
+
+    #[test]
+    fn only_pinging_inputs_carry_a_ping() {
+        assert_eq!(decode_ping(0), None);
+        assert_eq!(decode_ping(encode_ping(Vec2::new(100., 100.)) & !INPUT_PING), None);
+    }
+
+    #[test]
+    fn ping_stays_in_its_own_bits() {
+        let ping = encode_ping(Vec2::new(-1e6, 1e6)) | encode_ping(Vec2::new(1e6, -1e6));
+        assert_eq!(ping & !(INPUT_PING | 0xffff << INPUT_PING_X_SHIFT), 0);
+    }
+}
@@ -0,0 +1,278 @@
+use bevy::prelude::*;
+
+use crate::collision::OrientedRect;
+use crate::fog::FogOfWar;
+use crate::health::Health;
+use crate::status::{StatusEffects, StatusKind};
+use crate::{Player, HULL_SIZE};
+
+/// How far a shell that gets through the armor carries on into the hull in meters
+const PENETRATION_DEPTH: f32 = 1.5;
+/// Share of its hit points a thrown track or a knocked out turret ring is patched back up to
+const FIELD_REPAIR: f32 = 0.5;
+
+/// The parts of a hull that can be knocked out on their own
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ModuleKind {
+    LeftTrack,
+    RightTrack,
+    Engine,
+    TurretRing,
+    AmmoRack,
+}
+
+pub const MODULES: [ModuleKind; 5] = [
+    ModuleKind::LeftTrack,
+    ModuleKind::RightTrack,
+    ModuleKind::Engine,
+    ModuleKind::TurretRing,
+    ModuleKind::AmmoRack,
+];
+
+impl ModuleKind {
+    /// Where the module sits in the hull's local frame, the hull faces forward along Y
+    pub fn rect(self) -> OrientedRect {
+        let half_width = HULL_SIZE.x / 2.;
+        let half_length = HULL_SIZE.y / 2.;
+        let (center, half_extents) = match self {
+            ModuleKind::LeftTrack => (Vec2::new(0.2 - half_width, 0.), Vec2::new(0.2, half_length)),
+            ModuleKind::RightTrack => (Vec2::new(half_width - 0.2, 0.), Vec2::new(0.2, half_length)),
+            ModuleKind::Engine => (Vec2::new(0., 0.45 - half_length), Vec2::new(0.6, 0.45)),
+            ModuleKind::TurretRing => (Vec2::new(0., 0.3), Vec2::new(0.5, 0.5)),
+            ModuleKind::AmmoRack => (Vec2::new(0., -0.6), Vec2::new(0.45, 0.3)),
+        };
+        OrientedRect::axis_aligned(center, half_extents)
+    }
+
+    pub fn max_health(self) -> f32 {
+        match self {
+            ModuleKind::LeftTrack | ModuleKind::RightTrack => 40.,
+            ModuleKind::Engine => 50.,
+            ModuleKind::TurretRing => 40.,
+            ModuleKind::AmmoRack => 30.,
+        }
+    }
+
+    fn index(self) -> usize {
+        MODULES.iter().position(|&module| module == self).unwrap_or_default()
+    }
+}
+
+/// Hit points of each of a tank's modules, rolled back with the rest of the tank
+#[derive(Component, Clone)]
+pub struct Modules {
+    pub health: [f32; MODULES.len()],
+}
+
+impl Default for Modules {
+    fn default() -> Self {
+        Self {
+            health: MODULES.map(|module| module.max_health()),
+        }
+    }
+}
+
+impl Modules {
+    /// How intact a module is, from 0 for knocked out to 1 for undamaged
+    pub fn condition(&self, module: ModuleKind) -> f32 {
+        (self.health[module.index()] / module.max_health()).clamp(0., 1.)
+    }
+
+    /// Multiplier on top speed and acceleration, a damaged engine or tracks slow the tank down
+    pub fn speed_factor(&self) -> f32 {
+        let engine = 0.4 + 0.6 * self.condition(ModuleKind::Engine);
+        engine * self.track_factor()
+    }
+
+    /// Multiplier on hull rotation speed, the worse of the two tracks decides
+    pub fn track_factor(&self) -> f32 {
+        let tracks = self.condition(ModuleKind::LeftTrack).min(self.condition(ModuleKind::RightTrack));
+        0.5 + 0.5 * tracks
+    }
+
+    /// Multiplier on turret traverse speed
+    pub fn traverse_factor(&self) -> f32 {
+        0.3 + 0.7 * self.condition(ModuleKind::TurretRing)
+    }
+
+    /// Damages every module a penetrating shell passes through on its way into the hull
+    ///
+    /// A knocked out track throws the tank off its tracks and a knocked out turret ring jams the turret until the
+    /// crew patches them up, a knocked out engine catches fire and a knocked out ammo rack destroys the tank.
+    pub fn hit(
+        &mut self,
+        hull: &OrientedRect,
+        impact: Vec2,
+        direction: Vec2,
+        damage: f32,
+        status: &mut StatusEffects,
+        health: &mut Health,
+    ) {
+        let start = hull.local_point(impact);
+        let end = hull.local_point(impact + direction.normalize_or_zero() * PENETRATION_DEPTH);
+
+        for module in MODULES {
+            let index = module.index();
+            if self.health[index] <= 0. || module.rect().segment_hit(start, end).is_none() {
+                continue;
+            }
+
+            self.health[index] -= damage;
+            if self.health[index] > 0. {
+                continue;
+            }
+
+            match module {
+                ModuleKind::LeftTrack | ModuleKind::RightTrack => {
+                    status.apply(StatusKind::Immobilized);
+                    self.health[index] = module.max_health() * FIELD_REPAIR;
+                }
+                ModuleKind::TurretRing => {
+                    status.apply(StatusKind::Jammed);
+                    self.health[index] = module.max_health() * FIELD_REPAIR;
+                }
+                ModuleKind::Engine => status.apply(StatusKind::Burning),
+                ModuleKind::AmmoRack => health.current = 0.,
+            }
+        }
+    }
+}
+
+/// Outlines the damaged modules of the local team's tanks, shading from yellow to red as they get worse
+pub fn draw_modules(
+    player_query: Query<(&Player, &Modules, &Transform)>,
+    fog: Res<FogOfWar>,
+    mut gizmos: Gizmos,
+) {
+    for (ship, modules, transform) in &player_query {
+        if !fog.friendly_handles.contains(&ship.handle) {
+            continue;
+        }
+
+        let (_, _, angle) = transform.rotation.to_euler(EulerRot::XYZ);
+        for module in MODULES {
+            let condition = modules.condition(module);
+            if condition >= 1. {
+                continue;
+            }
+            let rect = module.rect();
+            let center = transform.translation.xy() + Vec2::from_angle(angle).rotate(rect.center);
+            gizmos.rect_2d(center, angle, rect.half_extents * 2., Color::rgb(1., condition, 0.));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    /// A hull at the origin facing up, with the damage it takes and what that leaves behind
+    struct Tank {
+        hull: OrientedRect,
+        modules: Modules,
+        status: StatusEffects,
+        health: Health,
+    }
+
+    impl Tank {
+        fn new(transform: Transform) -> Self {
+            Self {
+                hull: OrientedRect::from_transform(&transform, HULL_SIZE),
+                modules: Modules::default(),
+                status: StatusEffects::default(),
+                health: Health::new(100.),
+            }
+        }
+
+        fn hit(&mut self, impact: Vec2, direction: Vec2, damage: f32) {
+            self.modules
+                .hit(&self.hull, impact, direction, damage, &mut self.status, &mut self.health);
+        }
+    }
+
+    fn tank() -> Tank {
+        Tank::new(Transform::IDENTITY)
+    }
+
+    #[test]
+    fn damage_below_the_threshold_only_wears_a_module_down() {
+        let mut tank = tank();
+        let damage = ModuleKind::LeftTrack.max_health() - 1.;
+        // a side hit near the front only goes through the left track
+        tank.hit(Vec2::new(-1., 1.5), Vec2::X, damage);
+
+        assert_eq!(tank.modules.condition(ModuleKind::LeftTrack), 1. / ModuleKind::LeftTrack.max_health());
+        assert_eq!(tank.modules.condition(ModuleKind::RightTrack), 1.);
+        assert!(tank.status.effects.is_empty());
+        assert!(tank.modules.track_factor() < 1.);
+    }
+
+    #[test]
+    fn knocked_out_tracks_throw_the_tank_and_get_patched_up() {
+        let mut tank = tank();
+        tank.hit(Vec2::new(-1., 1.5), Vec2::X, ModuleKind::LeftTrack.max_health());
+
+        assert!(tank.status.immobilized());
+        assert_eq!(tank.modules.condition(ModuleKind::LeftTrack), FIELD_REPAIR);
+        assert_eq!(tank.health.current, 100.);
+    }
+
+    #[test]
+    fn knocked_out_turret_rings_jam_the_turret() {
+        let mut tank = tank();
+        tank.hit(Vec2::new(0., 2.), -Vec2::Y, ModuleKind::TurretRing.max_health());
+
+        assert!(tank.status.jammed());
+        assert!(!tank.status.immobilized());
+        assert_eq!(tank.modules.condition(ModuleKind::TurretRing), FIELD_REPAIR);
+        assert!(tank.modules.traverse_factor() < 1.);
+    }
+
+    #[test]
+    fn knocked_out_engines_burn_and_stay_out() {
+        let mut tank = tank();
+        // from the right, low on the hull, through the right track and the engine
+        tank.hit(Vec2::new(1., -1.5), -Vec2::X, ModuleKind::Engine.max_health());
+
+        assert!(tank.status.has(StatusKind::Burning));
+        assert!(tank.status.immobilized());
+        assert_eq!(tank.modules.condition(ModuleKind::Engine), 0.);
+        assert_eq!(tank.modules.speed_factor(), 0.4 * tank.modules.track_factor());
+
+        tank.hit(Vec2::new(1., -1.5), -Vec2::X, ModuleKind::Engine.max_health());
+        let fires = tank.status.effects.iter().filter(|effect| effect.kind == StatusKind::Burning).count();
+        assert_eq!(fires, 1, "an engine that is already out doesn't catch fire again");
+    }
+
+    #[test]
+    fn knocked_out_ammo_racks_destroy_the_tank() {
+        let mut tank = tank();
+        // a rear hit goes through the engine and on into the ammo rack
+        let damage = ModuleKind::AmmoRack.max_health() - 1.;
+        tank.hit(Vec2::new(0., -2.), Vec2::Y, damage);
+        assert_eq!(tank.health.current, 100.);
+
+        tank.hit(Vec2::new(0., -2.), Vec2::Y, 1.);
+        assert_eq!(tank.health.current, 0.);
+    }
+
+    #[test]
+    fn shells_that_stop_short_of_a_module_leave_it_alone() {
+        let mut tank = tank();
+        // a head on hit stops in the turret ring before reaching the ammo rack behind it
+        tank.hit(Vec2::new(0., 2.), -Vec2::Y, 1000.);
+        assert_eq!(tank.health.current, 100.);
+        assert_eq!(tank.modules.condition(ModuleKind::AmmoRack), 1.);
+    }
+
+    #[test]
+    fn modules_turn_with_the_hull() {
+        // facing left, so the left track is now along the bottom
+        let mut tank = Tank::new(Transform::from_rotation(Quat::from_rotation_z(FRAC_PI_2)));
+        tank.hit(Vec2::new(1.5, -1.), Vec2::Y, ModuleKind::LeftTrack.max_health());
+
+        assert!(tank.status.immobilized());
+        assert_eq!(tank.modules.condition(ModuleKind::RightTrack), 1.);
+    }
+}
@@ -0,0 +1,390 @@
+use bevy::prelude::*;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::bot::Bot;
+use crate::collision::OrientedRect;
+use crate::fog::standing_obstacles;
+use crate::map::MapData;
+use crate::obstacle::{Destructible, Obstacle};
+use crate::{HULL_SIZE, MAP_SIZE};
+
+/// Width and height of a navigation cell in meters
+pub const NAV_CELL_SIZE: f32 = 2.5;
+/// Number of navigation cells along each side of the map
+const NAV_CELLS_PER_SIDE: usize = (MAP_SIZE as f32 / NAV_CELL_SIZE) as usize;
+/// How far a cell centre has to be from an obstacle for a hull to fit through in meters
+const CLEARANCE: f32 = HULL_SIZE.x / 2. + 0.5;
+/// Cost of crossing a cell of ordinary ground, slower ground costs more
+const BASE_COST: u32 = 10;
+/// Most cells a single search looks at before giving up, keeps a hopeless search from stalling the frame
+const MAX_EXPANSIONS: usize = 40_000;
+/// How far around a blocked goal the search looks for somewhere it can reach, in cells
+const GOAL_SEARCH: i32 = 4;
+/// How far around each bot the debug overlay shows blocked cells in meters
+const DEBUG_RADIUS: f32 = 30.;
+
+/// Which cells tanks can drive through and how expensive they are, patched up whenever an obstacle is placed or
+/// destroyed
+///
+/// Everything in here follows from rolled back state, so every peer's bots find the same paths.
+#[derive(Resource, Default)]
+pub struct NavGrid {
+    /// cost of entering each cell, zero for cells a hull doesn't fit in, indexed by `y * NAV_CELLS_PER_SIDE + x`
+    costs: Vec<u32>,
+    /// cost of entering each cell with no obstacles around, from the map's terrain alone
+    ground: Vec<u32>,
+    /// every obstacle the grid was built around, sorted by `signature`
+    built_for: Vec<OrientedRect>,
+    /// width and height of the map's playable area
+    bounds: Vec2,
+}
+
+impl NavGrid {
+    /// The cell containing a world position
+    pub fn cell_at(pos: Vec2) -> Option<(usize, usize)> {
+        let cell = ((pos + MAP_SIZE as f32 / 2.) / NAV_CELL_SIZE).floor();
+        let range = 0.0..NAV_CELLS_PER_SIDE as f32;
+        (range.contains(&cell.x) && range.contains(&cell.y)).then_some((cell.x as usize, cell.y as usize))
+    }
+
+    /// The cell containing a world position, or the closest cell on the edge of the map for positions off it
+    fn cell_clamped(pos: Vec2) -> (usize, usize) {
+        let cell = ((pos + MAP_SIZE as f32 / 2.) / NAV_CELL_SIZE).floor();
+        let cell = cell.clamp(Vec2::ZERO, Vec2::splat((NAV_CELLS_PER_SIDE - 1) as f32));
+        (cell.x as usize, cell.y as usize)
+    }
+
+    /// World position of the centre of a cell
+    pub fn cell_center(x: usize, y: usize) -> Vec2 {
+        (Vec2::new(x as f32, y as f32) + 0.5) * NAV_CELL_SIZE - MAP_SIZE as f32 / 2.
+    }
+
+    /// Width and height of the playable area the grid was built for
+    pub fn bounds(&self) -> Vec2 {
+        self.bounds
+    }
+
+    pub fn passable(&self, x: usize, y: usize) -> bool {
+        self.costs.get(y * NAV_CELLS_PER_SIDE + x).is_some_and(|&cost| cost > 0)
+    }
+
+    /// Builds the grid from scratch from the map's terrain and the obstacles standing
+    fn build(&mut self, map: &MapData, obstacles: &[OrientedRect]) {
+        self.bounds = map.bounds;
+        let half_bounds = map.bounds / 2.;
+        self.ground = (0..NAV_CELLS_PER_SIDE * NAV_CELLS_PER_SIDE)
+            .map(|index| {
+                let center = Self::cell_center(index % NAV_CELLS_PER_SIDE, index / NAV_CELLS_PER_SIDE);
+                if center.abs().cmpgt(half_bounds).any() {
+                    return 0;
+                }
+                // slow ground costs as much more as it takes longer to cross
+                let max_speed = map.terrain.at(center).modifiers().max_speed;
+                (BASE_COST as f32 / max_speed).round() as u32
+            })
+            .collect();
+        self.costs = self.ground.clone();
+
+        for obstacle in obstacles {
+            self.block(obstacle, obstacle);
+        }
+        self.built_for = obstacles.to_vec();
+    }
+
+    /// Brings the grid in line with the obstacles standing now, only going over the cells around the obstacles that
+    /// were placed or destroyed since it was last built
+    fn update(&mut self, obstacles: &[OrientedRect]) {
+        let (placed, removed) = diff_sorted(obstacles, &self.built_for);
+
+        // cells around a destroyed obstacle go back to bare ground, then everything still standing nearby blocks
+        // them again
+        for gone in &removed {
+            let (min_x, min_y, max_x, max_y) = footprint(gone);
+            for y in min_y..=max_y {
+                let row = y * NAV_CELLS_PER_SIDE;
+                self.costs[row + min_x..=row + max_x].copy_from_slice(&self.ground[row + min_x..=row + max_x]);
+            }
+            for obstacle in obstacles {
+                self.block(obstacle, gone);
+            }
+        }
+        for obstacle in &placed {
+            self.block(obstacle, obstacle);
+        }
+
+        self.built_for = obstacles.to_vec();
+    }
+
+    /// Blocks the cells a hull doesn't fit in next to `obstacle`, only within the footprint of `within`
+    fn block(&mut self, obstacle: &OrientedRect, within: &OrientedRect) {
+        let (min_x, min_y, max_x, max_y) = footprint(obstacle);
+        let (within_min_x, within_min_y, within_max_x, within_max_y) = footprint(within);
+        for y in min_y.max(within_min_y)..=max_y.min(within_max_y) {
+            for x in min_x.max(within_min_x)..=max_x.min(within_max_x) {
+                if obstacle.distance_to(Self::cell_center(x, y)) < CLEARANCE {
+                    self.costs[y * NAV_CELLS_PER_SIDE + x] = 0;
+                }
+            }
+        }
+    }
+
+    /// Cheapest route from `start` to `goal` as a list of world positions, with the next one to drive to last
+    ///
+    /// A* over the cells with eight neighbours, a diagonal step can't cut the corner of a blocked cell. A blocked goal
+    /// is swapped for the closest cell around it that can be reached.
+    pub fn find_path(&self, start: Vec2, goal: Vec2) -> Option<Vec<Vec2>> {
+        let start = Self::cell_at(start)?;
+        let goal = self.reachable_near(Self::cell_at(goal)?)?;
+        let index = |(x, y): (usize, usize)| y * NAV_CELLS_PER_SIDE + x;
+        let min_cost = (BASE_COST as f32 / 1.3).floor() as u32;
+        // octile distance scaled by the cheapest ground, never more than the real cost
+        let heuristic = |(x, y): (usize, usize)| {
+            let dx = x.abs_diff(goal.0) as u32;
+            let dy = y.abs_diff(goal.1) as u32;
+            min_cost * (dx.max(dy) * 10 + dx.min(dy) * 4) / 10
+        };
+
+        let mut best = vec![u32::MAX; self.costs.len()];
+        let mut came_from = vec![usize::MAX; self.costs.len()];
+        // ties are broken by cell index so every peer expands the cells in the same order
+        let mut open = BinaryHeap::new();
+        best[index(start)] = 0;
+        open.push(Reverse((heuristic(start), index(start))));
+
+        let mut expansions = 0;
+        while let Some(Reverse((estimate, current))) = open.pop() {
+            // a cell can be queued again after a cheaper way to it turns up, skip the stale entries
+            let current_cell = (current % NAV_CELLS_PER_SIDE, current / NAV_CELLS_PER_SIDE);
+            if estimate > best[current] + heuristic(current_cell) {
+                continue;
+            }
+            if current == index(goal) {
+                return Some(self.trace(&came_from, index(start), current));
+            }
+            expansions += 1;
+            if expansions > MAX_EXPANSIONS {
+                return None;
+            }
+
+            let (x, y) = (current_cell.0 as i32, current_cell.1 as i32);
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                let Some(next) = self.cell(x + dx, y + dy) else {
+                    continue;
+                };
+                if !self.passable(next.0, next.1) {
+                    continue;
+                }
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && (self.cell(x + dx, y).is_none_or(|(cx, cy)| !self.passable(cx, cy))
+                    || self.cell(x, y + dy).is_none_or(|(cx, cy)| !self.passable(cx, cy)))
+                {
+                    continue;
+                }
+
+                let step = self.costs[index(next)];
+                let step = if diagonal { step * 14 / 10 } else { step };
+                let cost = best[current] + step;
+                if cost < best[index(next)] {
+                    best[index(next)] = cost;
+                    came_from[index(next)] = current;
+                    open.push(Reverse((cost + heuristic(next), index(next))));
+                }
+            }
+        }
+        None
+    }
+
+    fn cell(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let range = 0..NAV_CELLS_PER_SIDE as i32;
+        (range.contains(&x) && range.contains(&y)).then_some((x as usize, y as usize))
+    }
+
+    /// The goal cell itself if it can be reached, otherwise the closest one around it that can
+    fn reachable_near(&self, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+        (0..=GOAL_SEARCH).find_map(|radius| {
+            (-radius..=radius)
+                .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+                .filter(|(dx, dy)| dx.abs().max(dy.abs()) == radius)
+                .filter_map(|(dx, dy)| self.cell(x as i32 + dx, y as i32 + dy))
+                .find(|&(cx, cy)| self.passable(cx, cy))
+        })
+    }
+
+    /// Walks back from the goal, keeping only the cells where the route changes direction
+    fn trace(&self, came_from: &[usize], start: usize, goal: usize) -> Vec<Vec2> {
+        let mut points = vec![];
+        let mut cell = goal;
+        while cell != start && cell != usize::MAX {
+            points.push(Self::cell_center(cell % NAV_CELLS_PER_SIDE, cell / NAV_CELLS_PER_SIDE));
+            cell = came_from[cell];
+        }
+
+        (0..points.len())
+            .filter(|&i| {
+                let (Some(&after), Some(&before)) = (i.checked_sub(1).map(|i| &points[i]), points.get(i + 1)) else {
+                    return true;
+                };
+                let point = points[i];
+                (point - after).normalize_or_zero().dot((before - point).normalize_or_zero()) < 0.999
+            })
+            .map(|i| points[i])
+            .collect()
+    }
+}
+
+fn signature(obstacle: &OrientedRect) -> [f32; 6] {
+    [
+        obstacle.center.x,
+        obstacle.center.y,
+        obstacle.half_extents.x,
+        obstacle.half_extents.y,
+        obstacle.x_axis.x,
+        obstacle.x_axis.y,
+    ]
+}
+
+fn compare(a: &OrientedRect, b: &OrientedRect) -> Ordering {
+    signature(a).partial_cmp(&signature(b)).unwrap_or(Ordering::Equal)
+}
+
+/// Cells within clearance of an obstacle whichever way it is turned, as min x, min y, max x and max y
+fn footprint(obstacle: &OrientedRect) -> (usize, usize, usize, usize) {
+    let reach = Vec2::splat(obstacle.half_extents.length() + CLEARANCE);
+    let (min_x, min_y) = NavGrid::cell_clamped(obstacle.center - reach);
+    let (max_x, max_y) = NavGrid::cell_clamped(obstacle.center + reach);
+    (min_x, min_y, max_x, max_y)
+}
+
+/// The obstacles in `now` that weren't in `before` and the ones in `before` that are gone, both lists sorted
+fn diff_sorted(now: &[OrientedRect], before: &[OrientedRect]) -> (Vec<OrientedRect>, Vec<OrientedRect>) {
+    let (mut placed, mut removed) = (Vec::new(), Vec::new());
+    let (mut now, mut before) = (now.iter().peekable(), before.iter().peekable());
+    loop {
+        match (now.peek(), before.peek()) {
+            (Some(a), Some(b)) => match compare(a, b) {
+                Ordering::Less => placed.extend(now.next()),
+                Ordering::Greater => removed.extend(before.next()),
+                Ordering::Equal => {
+                    now.next();
+                    before.next();
+                }
+            },
+            (Some(_), None) => placed.extend(now.next()),
+            (None, Some(_)) => removed.extend(before.next()),
+            (None, None) => return (placed, removed),
+        }
+    }
+}
+
+/// Keeps the navigation grid in line with the obstacles standing
+///
+/// The grid is built from scratch when a map is loaded, after that placing or destroying an obstacle only touches
+/// the cells around it, which keeps barricades cheap even when rollback replays the frames they changed on.
+pub fn update_nav_grid(
+    map: Res<MapData>,
+    mut grid: ResMut<NavGrid>,
+    obstacle_query: Query<(&Obstacle, &Transform, Option<&Destructible>)>,
+) {
+    let mut obstacles = standing_obstacles(&obstacle_query);
+    // query order isn't guaranteed, sorting lets the obstacles be compared one by one
+    obstacles.sort_by(compare);
+
+    if grid.costs.is_empty() || map.is_changed() {
+        grid.build(&map, &obstacles);
+    } else if grid.built_for.len() != obstacles.len()
+        || grid.built_for.iter().zip(&obstacles).any(|(built, obstacle)| compare(built, obstacle).is_ne())
+    {
+        grid.update(&obstacles);
+    }
+}
+
+/// Whether the navigation debug overlay is shown
+#[derive(Resource, Default)]
+pub struct NavDebug(bool);
+
+/// Toggles the navigation debug overlay with F3
+pub fn toggle_nav_debug(
+    keys: Res<Input<KeyCode>>,
+    mut debug: ResMut<NavDebug>,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        debug.0 = !debug.0;
+    }
+}
+
+/// Draws each bot's planned route and the blocked cells around it
+pub fn draw_nav_debug(
+    debug: Res<NavDebug>,
+    grid: Res<NavGrid>,
+    bot_query: Query<(&Bot, &Transform)>,
+    mut gizmos: Gizmos,
+) {
+    if !debug.0 {
+        return;
+    }
+
+    for (bot, transform) in &bot_query {
+        let position = transform.translation.xy();
+
+        let (min_x, min_y) = NavGrid::cell_clamped(position - DEBUG_RADIUS);
+        let (max_x, max_y) = NavGrid::cell_clamped(position + DEBUG_RADIUS);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                if !grid.passable(x, y) {
+                    gizmos.rect_2d(NavGrid::cell_center(x, y), 0., Vec2::splat(NAV_CELL_SIZE * 0.8), Color::RED);
+                }
+            }
+        }
+
+        // the next waypoint is last, so the route is drawn from the tank outwards
+        gizmos.linestrip_2d(
+            std::iter::once(position).chain(bot.path.iter().rev().copied()),
+            Color::CYAN,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(mut obstacles: Vec<OrientedRect>) -> Vec<OrientedRect> {
+        obstacles.sort_by(compare);
+        obstacles
+    }
+
+    #[test]
+    fn patching_matches_a_full_rebuild() {
+        let map = MapData::default();
+        let wall = OrientedRect::axis_aligned(Vec2::new(20., 0.), Vec2::new(2., 15.));
+        let crate_ = OrientedRect::axis_aligned(Vec2::new(23., 5.), Vec2::new(1., 1.));
+        let barricade = OrientedRect {
+            center: Vec2::new(-10., 8.),
+            half_extents: Vec2::new(2., 0.5),
+            x_axis: Vec2::from_angle(0.7),
+        };
+
+        let mut patched = NavGrid::default();
+        patched.build(&map, &sorted(vec![wall, crate_]));
+        // the crate is destroyed while a barricade goes down somewhere else
+        let after = sorted(vec![wall, barricade]);
+        patched.update(&after);
+
+        let mut rebuilt = NavGrid::default();
+        rebuilt.build(&map, &after);
+        assert!(patched.costs == rebuilt.costs);
+    }
+
+    #[test]
+    fn path_goes_around_a_wall() {
+        let map = MapData::default();
+        let wall = OrientedRect::axis_aligned(Vec2::ZERO, Vec2::new(1., 20.));
+        let mut grid = NavGrid::default();
+        grid.build(&map, &[wall]);
+
+        let path = grid.find_path(Vec2::new(-10., 0.), Vec2::new(10., 0.)).expect("no path around the wall");
+        assert!(path.iter().all(|&point| wall.distance_to(point) >= CLEARANCE));
+        assert!(path.iter().any(|point| point.y.abs() > 20.));
+    }
+}
@@ -0,0 +1,32 @@
+use bevy::prelude::*;
+
+use crate::map::MapData;
+
+/// Radius of an objective when the map doesn't say, in meters
+pub const DEFAULT_OBJECTIVE_RADIUS: f32 = 10.;
+const OBJECTIVE_COLOR: Color = Color::GOLD;
+
+/// An area of the map worth fighting over, as described by the map
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectiveDef {
+    /// centre in world coordinates
+    pub center: Vec2,
+    /// in meters
+    pub radius: f32,
+}
+
+/// Rings every objective on the map
+pub fn draw_objectives(
+    map: Res<MapData>,
+    mut gizmos: Gizmos,
+) {
+    for objective in &map.objectives {
+        draw_objective(&mut gizmos, objective.center, objective.radius);
+    }
+}
+
+/// An objective's ring with a smaller one marking its centre, shared with the editor
+pub fn draw_objective(gizmos: &mut Gizmos, center: Vec2, radius: f32) {
+    gizmos.circle_2d(center, radius, OBJECTIVE_COLOR);
+    gizmos.circle_2d(center, 1., OBJECTIVE_COLOR);
+}
@@ -0,0 +1,131 @@
+use bevy::prelude::*;
+use bevy_ggrs::*;
+
+use crate::collision::OrientedRect;
+use crate::map::MapData;
+
+/// The kinds of obstacle a map can place
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ObstacleKind {
+    Wall,
+    Rock,
+    Building,
+}
+
+impl ObstacleKind {
+    pub fn color(self) -> Color {
+        match self {
+            ObstacleKind::Wall => Color::rgb(0.2, 0.2, 0.22),
+            ObstacleKind::Rock => Color::rgb(0.42, 0.38, 0.33),
+            ObstacleKind::Building => Color::rgb(0.55, 0.3, 0.25),
+        }
+    }
+
+    /// Name the map file uses for the obstacle
+    pub fn name(self) -> &'static str {
+        match self {
+            ObstacleKind::Wall => "wall",
+            ObstacleKind::Rock => "rock",
+            ObstacleKind::Building => "building",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wall" => Some(ObstacleKind::Wall),
+            "rock" => Some(ObstacleKind::Rock),
+            "building" => Some(ObstacleKind::Building),
+            _ => None,
+        }
+    }
+}
+
+/// An obstacle as described by the map file
+#[derive(Clone, Debug)]
+pub struct ObstacleDef {
+    pub kind: ObstacleKind,
+    /// centre in world coordinates
+    pub center: Vec2,
+    /// width and height in meters
+    pub size: Vec2,
+    /// hit points for destructible obstacles, `None` for obstacles that can't be destroyed
+    pub health: Option<f32>,
+}
+
+/// Axis aligned block that stops tanks and projectiles
+#[derive(Component, Clone)]
+pub struct Obstacle {
+    pub half_extents: Vec2,
+}
+
+impl Obstacle {
+    pub fn rect(&self, transform: &Transform) -> OrientedRect {
+        OrientedRect::axis_aligned(transform.translation.xy(), self.half_extents)
+    }
+}
+
+/// Hit points of an obstacle that can be shot down, rolled back so every peer agrees on what's still standing
+#[derive(Component, Clone)]
+pub struct Destructible {
+    pub health: f32,
+    pub max_health: f32,
+}
+
+impl Destructible {
+    pub fn destroyed(&self) -> bool {
+        self.health <= 0.
+    }
+}
+
+/// Whether an obstacle still blocks tanks and shots
+pub fn is_standing(destructible: Option<&Destructible>) -> bool {
+    destructible.is_none_or(|destructible| !destructible.destroyed())
+}
+
+/// Spawns the obstacles described by the map and the walls around it
+pub fn spawn_obstacles(
+    mut commands: Commands,
+    map: Res<MapData>,
+) {
+    for obstacle in map.obstacles.iter().chain(&map.boundary_walls()) {
+        let mut entity = commands.spawn((
+            SpriteBundle {
+                transform: Transform::from_translation(Vec3::from((obstacle.center, 50.))),
+                sprite: Sprite {
+                    color: obstacle.kind.color(),
+                    custom_size: Some(obstacle.size),
+                    ..default()
+                },
+                ..default()
+            },
+            Obstacle {
+                half_extents: obstacle.size / 2.,
+            },
+        ));
+
+        if let Some(health) = obstacle.health {
+            entity
+                .insert(Destructible {
+                    health,
+                    max_health: health,
+                })
+                .add_rollback();
+        }
+    }
+}
+
+/// Fades damaged obstacles out and hides the ones that have been destroyed
+pub fn draw_destructibles(
+    mut obstacle_query: Query<(&Destructible, &mut Sprite, &mut Visibility)>,
+) {
+    for (destructible, mut sprite, mut visibility) in &mut obstacle_query {
+        *visibility = if destructible.destroyed() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+
+        let health = (destructible.health / destructible.max_health).clamp(0., 1.);
+        sprite.color = sprite.color.with_a(0.5 + health * 0.5);
+    }
+}
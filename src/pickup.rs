@@ -0,0 +1,293 @@
+use bevy::prelude::*;
+use bevy_ggrs::*;
+
+use crate::collision::OrientedRect;
+use crate::fog::FogOfWar;
+use crate::health::Health;
+use crate::map::MapData;
+use crate::weapon::Armament;
+use crate::{Player, HULL_SIZE};
+
+/// How close the hull has to get to a pickup to collect it in meters
+const PICKUP_RADIUS: f32 = 1.;
+/// Seconds before a collected pickup comes back when the map doesn't say
+pub const DEFAULT_RESPAWN_TIME: f32 = 30.;
+/// Every kind of pickup, in the order maps hand them out and the editor cycles through them
+pub const PICKUP_KINDS: [PickupKind; 5] = [
+    PickupKind::Repair,
+    PickupKind::Ammo,
+    PickupKind::Speed,
+    PickupKind::Damage,
+    PickupKind::Shield,
+];
+/// Share of a tank's maximum health a repair kit restores
+const REPAIR_FRACTION: f32 = 0.5;
+/// Multiplier on top speed and acceleration while a speed boost lasts
+const SPEED_BOOST: f32 = 1.4;
+/// Multiplier on shell damage while a damage boost lasts
+const DAMAGE_BOOST: f32 = 1.5;
+
+/// The kinds of pickup a map can place
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PickupKind {
+    /// restores part of the tank's health
+    Repair,
+    /// faster top speed and acceleration for a while
+    Speed,
+    /// more damage per shell for a while
+    Damage,
+    /// no damage taken for a while
+    Shield,
+    /// refills every weapon
+    Ammo,
+}
+
+impl PickupKind {
+    pub fn color(self) -> Color {
+        match self {
+            PickupKind::Repair => Color::LIME_GREEN,
+            PickupKind::Speed => Color::CYAN,
+            PickupKind::Damage => Color::CRIMSON,
+            PickupKind::Shield => Color::GOLD,
+            PickupKind::Ammo => Color::BEIGE,
+        }
+    }
+
+    /// Name the map file uses for the pickup
+    pub fn name(self) -> &'static str {
+        match self {
+            PickupKind::Repair => "repair",
+            PickupKind::Speed => "speed",
+            PickupKind::Damage => "damage",
+            PickupKind::Shield => "shield",
+            PickupKind::Ammo => "ammo",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "repair" => Some(PickupKind::Repair),
+            "speed" => Some(PickupKind::Speed),
+            "damage" => Some(PickupKind::Damage),
+            "shield" => Some(PickupKind::Shield),
+            "ammo" => Some(PickupKind::Ammo),
+            _ => None,
+        }
+    }
+
+    /// Seconds the effect lasts, zero for pickups that act at once
+    pub fn duration(self) -> f32 {
+        match self {
+            PickupKind::Speed => 10.,
+            PickupKind::Damage => 10.,
+            PickupKind::Shield => 6.,
+            PickupKind::Repair | PickupKind::Ammo => 0.,
+        }
+    }
+}
+
+/// A pickup spawn point as described by the map file
+#[derive(Clone, Debug)]
+pub struct PickupDef {
+    pub kind: PickupKind,
+    /// centre in world coordinates
+    pub center: Vec2,
+    /// seconds before the pickup comes back after being collected
+    pub respawn_time: f32,
+}
+
+/// A pickup spawn point, rolled back so every peer agrees on who collected it and when it comes back
+#[derive(Component, Clone)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    pub respawn_time: f32,
+    /// seconds until the pickup is back, zero while it is waiting to be collected
+    pub cooldown: f32,
+}
+
+impl Pickup {
+    pub fn available(&self) -> bool {
+        self.cooldown <= 0.
+    }
+
+    /// Takes the pickup off the map until its respawn time has passed
+    pub fn collect(&mut self) {
+        self.cooldown = self.respawn_time;
+    }
+
+    /// Counts the respawn down by `delta` seconds
+    pub fn tick(&mut self, delta: f32) {
+        self.cooldown = (self.cooldown - delta).max(0.);
+    }
+}
+
+/// Seconds left on each timed pickup effect of a tank, rolled back with the rest of the tank
+#[derive(Component, Clone, Default)]
+pub struct PowerUps {
+    pub speed: f32,
+    pub damage: f32,
+    pub shield: f32,
+}
+
+impl PowerUps {
+    pub fn speed_factor(&self) -> f32 {
+        if self.speed > 0. { SPEED_BOOST } else { 1. }
+    }
+
+    pub fn damage_factor(&self) -> f32 {
+        if self.damage > 0. { DAMAGE_BOOST } else { 1. }
+    }
+
+    /// Damage that gets through to the hull, none while the shield is up
+    pub fn damage_taken(&self, damage: f32) -> f32 {
+        if self.shield > 0. { 0. } else { damage }
+    }
+}
+
+/// Spawns the pickups described by the map
+pub fn spawn_pickups(
+    mut commands: Commands,
+    map: Res<MapData>,
+) {
+    for pickup in &map.pickups {
+        commands
+            .spawn((
+                SpriteBundle {
+                    transform: Transform::from_translation(Vec3::from((pickup.center, 40.)))
+                        .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+                    sprite: Sprite {
+                        color: pickup.kind.color(),
+                        custom_size: Some(Vec2::splat(PICKUP_RADIUS * 1.5)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                Pickup {
+                    kind: pickup.kind,
+                    respawn_time: pickup.respawn_time,
+                    cooldown: 0.,
+                },
+            ))
+            .add_rollback();
+    }
+}
+
+/// Counts down pickup respawns and effects, and hands pickups to the tanks driving over them
+pub fn collect_pickups(
+    time: Res<Time>,
+    mut pickup_query: Query<(&mut Pickup, &Transform), Without<Player>>,
+    mut player_query: Query<(Entity, &Player, &Transform, &mut Health, &mut Armament, &mut PowerUps)>,
+) {
+    let delta = time.delta_seconds();
+
+    for (_, _, _, _, _, mut power_ups) in &mut player_query {
+        power_ups.speed = (power_ups.speed - delta).max(0.);
+        power_ups.damage = (power_ups.damage - delta).max(0.);
+        power_ups.shield = (power_ups.shield - delta).max(0.);
+    }
+
+    for (mut pickup, pickup_transform) in &mut pickup_query {
+        if !pickup.available() {
+            pickup.tick(delta);
+            continue;
+        }
+
+        // when two tanks reach a pickup on the same frame the lowest handle gets it, on every peer
+        let center = pickup_transform.translation.xy();
+        let Some(collector) = player_query
+            .iter()
+            .filter(|(_, _, ship_transform, health, _, _)| {
+                health.current > 0.
+                    && OrientedRect::from_transform(ship_transform, HULL_SIZE).distance_to(center) <= PICKUP_RADIUS
+            })
+            .min_by_key(|(_, ship, _, _, _, _)| ship.handle)
+            .map(|(entity, _, _, _, _, _)| entity)
+        else {
+            continue;
+        };
+        let Ok((_, _, _, mut health, mut armament, mut power_ups)) = player_query.get_mut(collector) else {
+            continue;
+        };
+
+        match pickup.kind {
+            PickupKind::Repair => {
+                health.current = (health.current + health.max * REPAIR_FRACTION).min(health.max);
+            }
+            PickupKind::Ammo => armament.ammo = Armament::default().ammo,
+            PickupKind::Speed => power_ups.speed = pickup.kind.duration(),
+            PickupKind::Damage => power_ups.damage = pickup.kind.duration(),
+            PickupKind::Shield => power_ups.shield = pickup.kind.duration(),
+        }
+        pickup.collect();
+    }
+}
+
+/// Hides pickups that have been collected and are waiting to come back
+pub fn draw_pickups(
+    mut pickup_query: Query<(&Pickup, &mut Visibility)>,
+) {
+    for (pickup, mut visibility) in &mut pickup_query {
+        *visibility = if pickup.available() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// Rings every visible tank that has a shield up
+pub fn draw_shields(
+    player_query: Query<(&Player, &PowerUps, &Transform)>,
+    fog: Res<FogOfWar>,
+    mut gizmos: Gizmos,
+) {
+    for (ship, power_ups, transform) in &player_query {
+        if power_ups.shield > 0. && fog.visible_handles.contains(&ship.handle) {
+            gizmos.circle_2d(transform.translation.xy(), HULL_SIZE.length() / 2. + 0.5, PickupKind::Shield.color());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pickup(respawn_time: f32) -> Pickup {
+        Pickup {
+            kind: PickupKind::Repair,
+            respawn_time,
+            cooldown: 0.,
+        }
+    }
+
+    #[test]
+    fn new_pickups_are_available() {
+        assert!(pickup(DEFAULT_RESPAWN_TIME).available());
+    }
+
+    #[test]
+    fn collected_pickups_come_back_after_their_respawn_time() {
+        let mut pickup = pickup(3.);
+        pickup.collect();
+        assert!(!pickup.available());
+
+        for _ in 0..5 {
+            pickup.tick(0.5);
+        }
+        assert!(!pickup.available(), "back after 2.5 of 3 seconds");
+
+        pickup.tick(0.5);
+        assert!(pickup.available());
+    }
+
+    #[test]
+    fn respawn_countdown_stops_at_zero() {
+        let mut pickup = pickup(1.);
+        pickup.collect();
+        pickup.tick(10.);
+        assert_eq!(pickup.cooldown, 0.);
+
+        pickup.collect();
+        assert_eq!(pickup.cooldown, 1., "a second collection waits the full time again");
+    }
+}
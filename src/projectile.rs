@@ -0,0 +1,467 @@
+use bevy::prelude::*;
+use bevy_ggrs::*;
+use std::f32::consts::{PI, TAU};
+
+use crate::armor::{Armor, HitOutcome};
+use crate::collision::OrientedRect;
+use crate::fog::FogOfWar;
+use crate::health::Health;
+use crate::obstacle::{is_standing, Destructible, Obstacle};
+use crate::pickup::PowerUps;
+use crate::rng::RollbackRng;
+use crate::module::Modules;
+use crate::status::StatusEffects;
+use crate::training::{Dummy, DummyDamage, TrainingOptions};
+use crate::weapon::{Armament, WeaponKind};
+use crate::{decode_aim, Player, TankInputs, Turret, HULL_SIZE, INPUT_FIRE};
+
+/// How far in front of the turret's centre shells appear
+const MUZZLE_OFFSET: f32 = 1.5;
+/// How far behind the hull dropped weapons land
+const DROP_OFFSET: f32 = 1.;
+/// Seconds even the closest lobbed shell spends in the air
+const MIN_FLIGHT_TIME: f32 = 1.;
+/// Share of speed, damage, penetration and remaining range a shell keeps after glancing off armor
+const RICOCHET_RETAINED: f32 = 0.5;
+/// How far off the armor deflected shells start so they don't strike the same face again
+const RICOCHET_OFFSET: f32 = 0.05;
+
+type ObstacleHitQuery<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static Obstacle, &'static Transform, Option<&'static mut Destructible>),
+    Without<Projectile>,
+>;
+type TankHitQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Player,
+        &'static Transform,
+        &'static Armor,
+        &'static PowerUps,
+        &'static mut Health,
+        &'static mut StatusEffects,
+        &'static mut Modules,
+        Has<Dummy>,
+    ),
+    Without<Projectile>,
+>;
+
+/// A shell in flight
+#[derive(Component, Clone)]
+pub struct Projectile {
+    /// handle of the player who fired it, shells never hit their own tank
+    pub owner: usize,
+    /// meters per second
+    pub velocity: Vec2,
+    pub damage: f32,
+    /// millimetres of armor the shell can go through head on
+    pub penetration: f32,
+    /// meters around the impact that take damage
+    pub splash_radius: f32,
+    /// meters left before the shell falls harmlessly
+    pub range: f32,
+    /// which weapon fired it
+    pub weapon: WeaponKind,
+    /// seconds left before the shell can go off, it passes through everything until then
+    pub arming: f32,
+    /// flight of an indirect fire shell, which passes over everything until it lands
+    pub arc: Option<ShellArc>,
+}
+
+impl Projectile {
+    /// Counts the arming delay down by `delta` seconds, true once the shell is live
+    pub fn arm(&mut self, delta: f32) -> bool {
+        if self.arming > 0. {
+            self.arming -= delta;
+            return false;
+        }
+        true
+    }
+}
+
+/// Path of a lobbed shell from the muzzle to where it comes down
+#[derive(Clone)]
+pub struct ShellArc {
+    pub origin: Vec2,
+    pub landing: Vec2,
+    /// seconds from firing to landing
+    pub flight_time: f32,
+    /// seconds since firing
+    pub elapsed: f32,
+}
+
+/// What a shell ran into this frame
+enum Hit {
+    Obstacle(Entity),
+    /// the tank and the world space normal of the hull face that was struck
+    Tank(Entity, Vec2),
+}
+
+/// Switches to the weapon each player has selected and fires it if the trigger is held and it is loaded
+#[allow(clippy::too_many_arguments)]
+pub fn fire_projectiles(
+    mut commands: Commands,
+    inputs: Res<TankInputs>,
+    time: Res<Time>,
+    options: Res<TrainingOptions>,
+    mut rng: ResMut<RollbackRng>,
+    mut player_query: Query<(&Player, &Transform, &mut Armament, &PowerUps)>,
+    turret_query: Query<(&Turret, &Transform)>,
+    projectile_query: Query<&Projectile>,
+) {
+    for (ship, ship_transform, mut armament, power_ups) in &mut player_query {
+        let input = inputs[ship.handle];
+
+        armament.active = WeaponKind::from_input(input);
+        for cooldown in &mut armament.cooldowns {
+            *cooldown = (*cooldown - time.delta_seconds()).max(0.);
+        }
+
+        let slot = armament.active.slot();
+        if input & INPUT_FIRE == 0 || armament.cooldowns[slot] > 0. || armament.ammo[slot] == 0 {
+            continue;
+        }
+        let Some((_, tur_transform)) = turret_query.iter().find(|(turret, _)| turret.handle == ship.handle) else {
+            continue;
+        };
+
+        let weapon = armament.active.def();
+        let deployed = projectile_query
+            .iter()
+            .filter(|projectile| projectile.owner == ship.handle && projectile.weapon == armament.active)
+            .count();
+        if !weapon.can_deploy(deployed) {
+            continue;
+        }
+        if !options.no_reload {
+            armament.cooldowns[slot] = weapon.fire_interval;
+        }
+        if !options.infinite_ammo {
+            armament.ammo[slot] -= 1;
+        }
+
+        let mut arc = None;
+        let (position, rotation, velocity) = if weapon.indirect {
+            // lobbed weapons come down on the aim point, clamped to their range and scattered by their spread
+            let origin = tur_transform.translation.xy();
+            let to_aim = (decode_aim(input) - origin).clamp_length_max(weapon.range);
+            let scatter = Vec2::from_angle(rng.range(0., TAU)) * rng.range(0., weapon.spread.tan() * to_aim.length());
+            let landing = origin + to_aim + scatter;
+            arc = Some(ShellArc {
+                origin,
+                landing,
+                flight_time: (origin.distance(landing) / weapon.projectile_speed).max(MIN_FLIGHT_TIME),
+                elapsed: 0.,
+            });
+            (origin, tur_transform.rotation, Vec2::ZERO)
+        } else if weapon.projectile_speed > 0. {
+            // fired weapons leave the muzzle, strayed by up to the weapon's spread
+            let spread = Quat::from_rotation_z(rng.range(-weapon.spread, weapon.spread));
+            let rotation = tur_transform.rotation * spread;
+            let forward = (rotation * Vec3::Y).xy();
+            let muzzle = tur_transform.translation.xy() + forward * MUZZLE_OFFSET;
+            (muzzle, rotation, forward * weapon.projectile_speed)
+        } else {
+            // dropped weapons land behind the hull
+            let backward = -(ship_transform.rotation * Vec3::Y).xy();
+            let drop = ship_transform.translation.xy() + backward * (HULL_SIZE.y / 2. + DROP_OFFSET);
+            (drop, ship_transform.rotation, Vec2::ZERO)
+        };
+
+        commands
+            .spawn((
+                SpriteBundle {
+                    transform: Transform::from_translation(Vec3::from((position, 103.)))
+                        .with_rotation(rotation),
+                    sprite: Sprite {
+                        color: weapon.color,
+                        custom_size: Some(weapon.size),
+                        ..default()
+                    },
+                    ..default()
+                },
+                Projectile {
+                    owner: ship.handle,
+                    velocity,
+                    damage: weapon.damage * power_ups.damage_factor(),
+                    penetration: weapon.penetration,
+                    splash_radius: weapon.splash_radius,
+                    range: weapon.range,
+                    weapon: armament.active,
+                    arming: weapon.arming_time,
+                    arc,
+                },
+            ))
+            .add_rollback();
+    }
+}
+
+/// Moves shells along their path and stops them at the first obstacle or tank they run into
+pub fn move_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut dummy_damage: ResMut<DummyDamage>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut Transform, &Sprite)>,
+    mut obstacle_query: ObstacleHitQuery,
+    mut player_query: TankHitQuery,
+) {
+    for (entity, mut projectile, mut transform, sprite) in &mut projectile_query {
+        let (damage, splash_radius) = (projectile.damage, projectile.splash_radius);
+
+        // lobbed shells fly over everything and only explode where they land
+        if let Some(arc) = projectile.arc.as_mut() {
+            arc.elapsed += time.delta_seconds();
+            let progress = (arc.elapsed / arc.flight_time).min(1.);
+            transform.translation = Vec3::from((arc.origin.lerp(arc.landing, progress), transform.translation.z));
+            // the shell is drawn bigger near the top of its arc
+            transform.scale = Vec3::splat(1. + (progress * PI).sin());
+
+            if progress >= 1. {
+                splash(
+                    arc.landing,
+                    damage,
+                    splash_radius,
+                    None,
+                    &mut obstacle_query,
+                    &mut player_query,
+                    &mut dummy_damage,
+                );
+                commands.entity(entity).despawn_recursive();
+            }
+            continue;
+        }
+
+        if !projectile.arm(time.delta_seconds()) {
+            continue;
+        }
+
+        let start = transform.translation.xy();
+        let end = start + projectile.velocity * time.delta_seconds();
+
+        // find the closest thing along this frame's path
+        let mut closest: Option<(f32, Hit)> = None;
+
+        for (obstacle_entity, obstacle, obstacle_transform, destructible) in &obstacle_query {
+            if !is_standing(destructible) {
+                continue;
+            }
+            if let Some((t, _)) = obstacle.rect(obstacle_transform).segment_hit(start, end) {
+                if closest.as_ref().is_none_or(|(closest_t, _)| t < *closest_t) {
+                    closest = Some((t, Hit::Obstacle(obstacle_entity)));
+                }
+            }
+        }
+
+        for (ship_entity, ship, ship_transform, _, _, health, _, _, _) in &player_query {
+            // tanks that are down and waiting to come back aren't on the field
+            if ship.handle == projectile.owner || health.current <= 0. {
+                continue;
+            }
+            let hull = OrientedRect::from_transform(ship_transform, HULL_SIZE);
+            if let Some((t, normal)) = hull.segment_hit(start, end) {
+                if closest.as_ref().is_none_or(|(closest_t, _)| t < *closest_t) {
+                    closest = Some((t, Hit::Tank(ship_entity, normal)));
+                }
+            }
+        }
+
+        let Some((t, hit)) = closest else {
+            transform.translation = Vec3::from((end, transform.translation.z));
+            projectile.range -= start.distance(end);
+            if projectile.range <= 0. {
+                commands.entity(entity).despawn_recursive();
+            }
+            continue;
+        };
+
+        let impact = start.lerp(end, t);
+
+        // whatever was struck directly takes the full damage
+        match hit {
+            Hit::Obstacle(obstacle_entity) => {
+                if let Ok((_, _, _, Some(mut destructible))) = obstacle_query.get_mut(obstacle_entity) {
+                    destructible.health -= damage;
+                }
+            }
+            Hit::Tank(ship_entity, normal) => {
+                if let Ok((_, _, ship_transform, armor, power_ups, mut health, mut status, mut modules, dummy)) =
+                    player_query.get_mut(ship_entity)
+                {
+                    let hull = OrientedRect::from_transform(ship_transform, HULL_SIZE);
+                    match armor.hit(&hull, normal, projectile.velocity).outcome(projectile.penetration) {
+                        HitOutcome::Penetrated => {
+                            let taken = power_ups.damage_taken(damage);
+                            health.current -= taken;
+                            if dummy {
+                                dummy_damage.0 += taken;
+                            }
+                            // a shell that gets through carries on into the hull and wrecks whatever it passes
+                            // through, a mine goes off straight up into the belly
+                            if taken > 0. {
+                                let direction = if projectile.velocity == Vec2::ZERO {
+                                    -normal
+                                } else {
+                                    projectile.velocity
+                                };
+                                modules.hit(&hull, impact, direction, taken, &mut status, &mut health);
+                            }
+                        }
+                        HitOutcome::NotPenetrated => {}
+                        HitOutcome::Ricochet => {
+                            // the shell glances off the armor, loses some of its punch and carries on
+                            let deflected = projectile.velocity - 2. * projectile.velocity.dot(normal) * normal;
+                            let position = impact + normal * RICOCHET_OFFSET;
+                            commands
+                                .spawn((
+                                    SpriteBundle {
+                                        transform: Transform::from_translation(Vec3::from((position, transform.translation.z)))
+                                            .with_rotation(Quat::from_rotation_arc_2d(Vec2::Y, deflected.normalize())),
+                                        sprite: sprite.clone(),
+                                        ..default()
+                                    },
+                                    Projectile {
+                                        owner: projectile.owner,
+                                        velocity: deflected * RICOCHET_RETAINED,
+                                        damage: damage * RICOCHET_RETAINED,
+                                        penetration: projectile.penetration * RICOCHET_RETAINED,
+                                        splash_radius,
+                                        range: projectile.range * RICOCHET_RETAINED,
+                                        weapon: projectile.weapon,
+                                        arming: 0.,
+                                        arc: None,
+                                    },
+                                ))
+                                .add_rollback();
+                            commands.entity(entity).despawn_recursive();
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+
+        // everything else nearby takes splash damage that falls off with distance
+        let struck = match hit {
+            Hit::Obstacle(struck) | Hit::Tank(struck, _) => struck,
+        };
+        splash(
+            impact,
+            damage,
+            splash_radius,
+            Some(struck),
+            &mut obstacle_query,
+            &mut player_query,
+            &mut dummy_damage,
+        );
+
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Damages everything within the splash radius of an explosion apart from what it struck directly
+fn splash(
+    impact: Vec2,
+    damage: f32,
+    splash_radius: f32,
+    struck: Option<Entity>,
+    obstacle_query: &mut ObstacleHitQuery,
+    player_query: &mut TankHitQuery,
+    dummy_damage: &mut DummyDamage,
+) {
+    if splash_radius <= 0. {
+        return;
+    }
+
+    for (obstacle_entity, obstacle, obstacle_transform, destructible) in obstacle_query {
+        let Some(mut destructible) = destructible else {
+            continue;
+        };
+        if struck == Some(obstacle_entity) {
+            continue;
+        }
+        let distance = obstacle.rect(obstacle_transform).distance_to(impact);
+        destructible.health -= splash_damage(damage, splash_radius, distance);
+    }
+    for (ship_entity, _, ship_transform, _, power_ups, mut health, _, _, dummy) in player_query {
+        if struck == Some(ship_entity) || health.current <= 0. {
+            continue;
+        }
+        let distance = OrientedRect::from_transform(ship_transform, HULL_SIZE).distance_to(impact);
+        let taken = power_ups.damage_taken(splash_damage(damage, splash_radius, distance));
+        health.current -= taken;
+        if dummy {
+            dummy_damage.0 += taken;
+        }
+    }
+}
+
+/// Damage dealt at `distance` from an explosion, falling off linearly to nothing at the edge of the splash
+fn splash_damage(damage: f32, splash_radius: f32, distance: f32) -> f32 {
+    damage * (1. - distance / splash_radius).max(0.)
+}
+
+/// Marks where incoming artillery is going to land, the inner ring closes as the shell comes down
+///
+/// Enemy shells are only marked once their landing spot is in vision.
+pub fn draw_landing_indicators(
+    fog: Res<FogOfWar>,
+    projectile_query: Query<&Projectile>,
+    mut gizmos: Gizmos,
+) {
+    for projectile in &projectile_query {
+        let Some(arc) = &projectile.arc else {
+            continue;
+        };
+        if !fog.friendly_handles.contains(&projectile.owner) && !fog.is_lit(arc.landing) {
+            continue;
+        }
+        let remaining = 1. - (arc.elapsed / arc.flight_time).clamp(0., 1.);
+        gizmos.circle_2d(arc.landing, projectile.splash_radius, Color::RED);
+        gizmos.circle_2d(arc.landing, projectile.splash_radius * remaining, Color::ORANGE_RED);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mine() -> Projectile {
+        let weapon = WeaponKind::Mines.def();
+        Projectile {
+            owner: 0,
+            velocity: Vec2::ZERO,
+            damage: weapon.damage,
+            penetration: weapon.penetration,
+            splash_radius: weapon.splash_radius,
+            range: weapon.range,
+            weapon: WeaponKind::Mines,
+            arming: weapon.arming_time,
+            arc: None,
+        }
+    }
+
+    #[test]
+    fn mines_stay_inert_until_armed() {
+        let mut mine = mine();
+        let frame = 0.25;
+        let frames = (WeaponKind::Mines.def().arming_time / frame).ceil() as usize;
+        for _ in 0..frames {
+            assert!(!mine.arm(frame));
+        }
+        assert!(mine.arm(frame));
+        assert!(mine.arm(frame), "armed mines stay armed");
+    }
+
+    #[test]
+    fn shells_are_live_at_once() {
+        let mut shell = Projectile {
+            weapon: WeaponKind::Cannon,
+            arming: WeaponKind::Cannon.def().arming_time,
+            ..mine()
+        };
+        assert!(shell.arm(1. / 60.));
+    }
+}
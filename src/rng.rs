@@ -0,0 +1,40 @@
+use bevy::prelude::*;
+
+/// Seed every peer starts from
+const DEFAULT_SEED: u64 = 0x7a4e_b015_5eed_2024;
+
+/// Random numbers for the simulation, rolled back with everything else so every peer draws the same sequence
+#[derive(Resource, Clone)]
+pub struct RollbackRng(u64);
+
+impl Default for RollbackRng {
+    fn default() -> Self {
+        Self(DEFAULT_SEED)
+    }
+}
+
+impl RollbackRng {
+    /// A generator starting from a seed of our own, for anything else that needs a sequence every peer can reproduce
+    pub fn with_seed(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Next raw value, splitmix64
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0.0..1.0`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform value in `min..max`
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
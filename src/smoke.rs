@@ -0,0 +1,109 @@
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use bevy_ggrs::*;
+
+/// Radius of a smoke cloud in meters
+const SMOKE_RADIUS: f32 = 6.;
+/// Seconds a smoke cloud hangs around
+pub const SMOKE_DURATION: f32 = 12.;
+/// Seconds at the end of a cloud's life over which it thins out
+const SMOKE_FADE: f32 = 2.;
+
+/// A smoke cloud that blocks sight lines, rolled back so every peer agrees on where it is and how long it lasts
+#[derive(Component, Clone)]
+pub struct SmokeCloud {
+    /// handle of the player who laid it
+    pub owner: usize,
+    /// seconds until the cloud is gone
+    pub remaining: f32,
+}
+
+impl SmokeCloud {
+    /// Current radius in meters, the cloud shrinks as it thins out
+    pub fn radius(&self) -> f32 {
+        SMOKE_RADIUS * (self.remaining / SMOKE_FADE).clamp(0., 1.)
+    }
+}
+
+/// Mesh and material shared by every smoke cloud
+#[derive(Resource)]
+pub struct SmokeAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<ColorMaterial>,
+}
+
+pub fn setup_smoke(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(SmokeAssets {
+        mesh: meshes.add(shape::Circle::new(1.).into()),
+        material: materials.add(ColorMaterial::from(Color::rgba(0.8, 0.8, 0.8, 0.85))),
+    });
+}
+
+/// Lays a smoke cloud around a position
+pub fn spawn_smoke(commands: &mut Commands, assets: &SmokeAssets, owner: usize, position: Vec2) {
+    commands
+        .spawn((
+            MaterialMesh2dBundle {
+                mesh: assets.mesh.clone().into(),
+                material: assets.material.clone(),
+                transform: Transform::from_translation(Vec3::from((position, 104.)))
+                    .with_scale(Vec3::splat(SMOKE_RADIUS)),
+                ..default()
+            },
+            SmokeCloud {
+                owner,
+                remaining: SMOKE_DURATION,
+            },
+        ))
+        .add_rollback();
+}
+
+/// Thins smoke clouds out and clears them away once they are gone
+pub fn dissipate_smoke(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut smoke_query: Query<(Entity, &mut SmokeCloud, &mut Transform)>,
+) {
+    for (entity, mut cloud, mut transform) in &mut smoke_query {
+        cloud.remaining -= time.delta_seconds();
+        if cloud.remaining <= 0. {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        transform.scale = Vec3::splat(cloud.radius());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fog::line_of_sight;
+
+    fn cloud(remaining: f32) -> (Vec2, f32) {
+        let cloud = SmokeCloud { owner: 0, remaining };
+        (Vec2::new(10., 0.), cloud.radius())
+    }
+
+    #[test]
+    fn smoke_blocks_sight() {
+        let fresh = cloud(SMOKE_DURATION);
+        assert!(!line_of_sight(Vec2::ZERO, Vec2::new(20., 0.), &[], &[fresh]));
+        // a sight line grazing past the edge of the cloud still gets through
+        let offset = Vec2::new(0., SMOKE_RADIUS + 1.);
+        assert!(line_of_sight(offset, Vec2::new(20., 0.) + offset, &[], &[fresh]));
+    }
+
+    #[test]
+    fn smoke_shrinks_as_it_thins_out() {
+        assert_eq!(cloud(SMOKE_DURATION).1, SMOKE_RADIUS);
+        assert_eq!(cloud(SMOKE_FADE / 2.).1, SMOKE_RADIUS / 2.);
+        assert_eq!(cloud(0.).1, 0.);
+
+        let offset = Vec2::new(0., SMOKE_RADIUS / 2. + 1.);
+        assert!(!line_of_sight(offset, Vec2::new(20., 0.) + offset, &[], &[cloud(SMOKE_DURATION)]));
+        assert!(line_of_sight(offset, Vec2::new(20., 0.) + offset, &[], &[cloud(SMOKE_FADE / 2.)]));
+    }
+}
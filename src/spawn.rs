@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+
+use crate::HULL_SIZE;
+
+/// Seconds a tank can't be hurt for after spawning or respawning
+pub const SPAWN_PROTECTION: f32 = 3.;
+/// How close another tank can be to a spawn point before it counts as taken, in meters
+const OCCUPIED_RADIUS: f32 = HULL_SIZE.y * 1.5;
+
+/// A spawn point as described by the map
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnDef {
+    /// centre in world coordinates
+    pub center: Vec2,
+    /// the only team that spawns here, `None` for spawn points anyone can use
+    pub team: Option<usize>,
+}
+
+/// Every spawn point a tank can start from or come back at
+#[derive(Component, Clone)]
+pub struct SpawnPoints(pub Vec<Vec2>);
+
+/// Spawn points a team can use: the ones kept for it, or the shared ones when the map has none for it
+pub fn team_spawns(spawns: &[SpawnDef], team: usize) -> Vec<Vec2> {
+    let own: Vec<Vec2> = spawns
+        .iter()
+        .filter(|spawn| spawn.team == Some(team))
+        .map(|spawn| spawn.center)
+        .collect();
+    if !own.is_empty() {
+        return own;
+    }
+    spawns
+        .iter()
+        .filter(|spawn| spawn.team.is_none())
+        .map(|spawn| spawn.center)
+        .collect()
+}
+
+/// The candidate furthest from the closest enemy that nobody is sitting on
+///
+/// Taken spawn points are only used when every one of them is taken. Ties go to the earlier candidate so every peer
+/// picks the same one.
+pub fn safest_spawn(candidates: &[Vec2], enemies: &[Vec2], occupied: &[Vec2]) -> Option<Vec2> {
+    let score = |candidate: Vec2| {
+        if occupied.iter().any(|other| other.distance(candidate) < OCCUPIED_RADIUS) {
+            return f32::NEG_INFINITY;
+        }
+        enemies
+            .iter()
+            .map(|enemy| enemy.distance(candidate))
+            .fold(f32::INFINITY, f32::min)
+    };
+
+    let mut best: Option<(Vec2, f32)> = None;
+    for &candidate in candidates {
+        let candidate_score = score(candidate);
+        if best.is_none_or(|(_, best_score)| candidate_score > best_score) {
+            best = Some((candidate, candidate_score));
+        }
+    }
+    best.map(|(candidate, _)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPAWNS: [SpawnDef; 4] = [
+        SpawnDef { center: Vec2::new(-100., 0.), team: Some(0) },
+        SpawnDef { center: Vec2::new(100., 0.), team: Some(1) },
+        SpawnDef { center: Vec2::new(0., 100.), team: None },
+        SpawnDef { center: Vec2::new(0., -100.), team: None },
+    ];
+
+    #[test]
+    fn teams_use_their_own_spawns() {
+        assert_eq!(team_spawns(&SPAWNS, 0), [Vec2::new(-100., 0.)]);
+        assert_eq!(team_spawns(&SPAWNS, 1), [Vec2::new(100., 0.)]);
+    }
+
+    #[test]
+    fn teams_without_spawns_share_the_rest() {
+        assert_eq!(team_spawns(&SPAWNS, 2), [Vec2::new(0., 100.), Vec2::new(0., -100.)]);
+    }
+
+    #[test]
+    fn spawns_furthest_from_the_enemy() {
+        let candidates = [Vec2::new(-100., 0.), Vec2::new(100., 0.)];
+        assert_eq!(safest_spawn(&candidates, &[Vec2::new(80., 0.)], &[]), Some(candidates[0]));
+        assert_eq!(safest_spawn(&candidates, &[Vec2::new(-80., 0.)], &[]), Some(candidates[1]));
+        assert_eq!(safest_spawn(&[], &[], &[]), None);
+    }
+
+    #[test]
+    fn spawns_clear_of_other_tanks() {
+        let candidates = [Vec2::new(-100., 0.), Vec2::new(100., 0.)];
+        let enemies = [Vec2::new(90., 0.)];
+        assert_eq!(safest_spawn(&candidates, &enemies, &[Vec2::new(-101., 0.)]), Some(candidates[1]));
+        // when every spot is taken one of them still gets used
+        assert!(safest_spawn(&candidates, &enemies, &candidates).is_some());
+    }
+
+    #[test]
+    fn ties_go_to_the_first_candidate() {
+        let candidates = [Vec2::new(0., 50.), Vec2::new(0., -50.)];
+        assert_eq!(safest_spawn(&candidates, &[], &[]), Some(candidates[0]));
+        assert_eq!(safest_spawn(&candidates, &[Vec2::ZERO], &[]), Some(candidates[0]));
+    }
+}
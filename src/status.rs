@@ -0,0 +1,209 @@
+use bevy::prelude::*;
+
+use crate::fog::FogOfWar;
+use crate::health::Health;
+use crate::pickup::PowerUps;
+use crate::{
+    Player, INPUT_FORWARD, INPUT_LEFT_TRACK_FORWARD, INPUT_LEFT_TRACK_REVERSE, INPUT_REVERSE,
+    INPUT_RIGHT_TRACK_FORWARD, INPUT_RIGHT_TRACK_REVERSE,
+};
+
+/// Hit points per second each fire on a tank burns away
+const BURN_DAMAGE: f32 = 3.;
+/// Input bits that drive the tank forward or back, a thrown track ignores them
+const DRIVE_INPUTS: u64 = INPUT_FORWARD
+    | INPUT_REVERSE
+    | INPUT_LEFT_TRACK_FORWARD
+    | INPUT_LEFT_TRACK_REVERSE
+    | INPUT_RIGHT_TRACK_FORWARD
+    | INPUT_RIGHT_TRACK_REVERSE;
+
+/// Timed debuffs a hit can leave on a tank by knocking out one of its modules
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StatusKind {
+    /// a thrown track, the tank can't drive forward or back
+    Immobilized,
+    /// the turret ring is stuck and the turret can't traverse
+    Jammed,
+    /// a fire that burns hit points away every second
+    Burning,
+}
+
+impl StatusKind {
+    /// Seconds the effect lasts
+    pub fn duration(self) -> f32 {
+        match self {
+            StatusKind::Immobilized => 4.,
+            StatusKind::Jammed => 3.,
+            StatusKind::Burning => 6.,
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            StatusKind::Immobilized => Color::GRAY,
+            StatusKind::Jammed => Color::YELLOW,
+            StatusKind::Burning => Color::ORANGE_RED,
+        }
+    }
+}
+
+/// One running debuff
+#[derive(Clone)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// seconds until it wears off
+    pub remaining: f32,
+}
+
+/// The debuffs on a tank, rolled back so every peer agrees on when they start and wear off
+///
+/// Effects stack: every fire burns on its own, and a tank stays immobilized or jammed until the last
+/// effect of that kind wears off.
+#[derive(Component, Clone, Default)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    pub fn apply(&mut self, kind: StatusKind) {
+        self.effects.push(StatusEffect {
+            kind,
+            remaining: kind.duration(),
+        });
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    pub fn immobilized(&self) -> bool {
+        self.has(StatusKind::Immobilized)
+    }
+
+    pub fn jammed(&self) -> bool {
+        self.has(StatusKind::Jammed)
+    }
+
+    /// Drops the inputs the tank can't act on, a thrown track stops it driving anywhere until it is fixed
+    pub fn allowed_input(&self, input: u64) -> u64 {
+        if self.immobilized() {
+            input & !DRIVE_INPUTS
+        } else {
+            input
+        }
+    }
+
+    /// Wears every effect down by `delta` seconds and returns the damage fires did in that time
+    pub fn tick(&mut self, delta: f32) -> f32 {
+        let mut burned = 0.;
+        for effect in &mut self.effects {
+            let elapsed = delta.min(effect.remaining);
+            if effect.kind == StatusKind::Burning {
+                burned += BURN_DAMAGE * elapsed;
+            }
+            effect.remaining -= elapsed;
+        }
+        self.effects.retain(|effect| effect.remaining > 0.);
+        burned
+    }
+}
+
+/// Burns tanks that are on fire and wears off effects whose time is up
+pub fn tick_status_effects(
+    time: Res<Time>,
+    mut player_query: Query<(&mut StatusEffects, &mut Health, &PowerUps), With<Player>>,
+) {
+    let delta = time.delta_seconds();
+
+    for (mut status, mut health, power_ups) in &mut player_query {
+        let burned = status.tick(delta);
+        health.current -= power_ups.damage_taken(burned);
+    }
+}
+
+/// Draws a marker under the health bar of every visible tank for each kind of effect on it
+pub fn draw_status_effects(
+    player_query: Query<(&Player, &StatusEffects, &Transform)>,
+    fog: Res<FogOfWar>,
+    mut gizmos: Gizmos,
+) {
+    for (ship, status, transform) in &player_query {
+        if !fog.visible_handles.contains(&ship.handle) {
+            continue;
+        }
+
+        let start = transform.translation.xy() + Vec2::new(-1.5, 2.6);
+        let kinds = [StatusKind::Immobilized, StatusKind::Jammed, StatusKind::Burning];
+        for (index, kind) in kinds.into_iter().filter(|&kind| status.has(kind)).enumerate() {
+            gizmos.circle_2d(start + Vec2::new(index as f32 * 0.6 + 0.2, 0.), 0.2, kind.color());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{INPUT_FIRE, INPUT_LEFT, INPUT_RIGHT};
+
+    #[test]
+    fn effects_wear_off_after_their_duration() {
+        let mut status = StatusEffects::default();
+        status.apply(StatusKind::Jammed);
+        assert!(status.jammed());
+
+        status.tick(StatusKind::Jammed.duration() - 0.5);
+        assert!(status.jammed());
+        status.tick(0.5);
+        assert!(!status.jammed());
+        assert!(status.effects.is_empty());
+    }
+
+    #[test]
+    fn effects_of_the_same_kind_stack() {
+        let mut status = StatusEffects::default();
+        let duration = StatusKind::Immobilized.duration();
+        status.apply(StatusKind::Immobilized);
+        status.tick(duration / 2.);
+        status.apply(StatusKind::Immobilized);
+
+        // the first track wears off but the second keeps the tank stuck until it does too
+        status.tick(duration / 2.);
+        assert!(status.immobilized());
+        status.tick(duration / 2.);
+        assert!(!status.immobilized());
+    }
+
+    #[test]
+    fn every_fire_burns_on_its_own() {
+        let mut status = StatusEffects::default();
+        status.apply(StatusKind::Burning);
+        assert_eq!(status.tick(1.), BURN_DAMAGE);
+
+        status.apply(StatusKind::Burning);
+        assert_eq!(status.tick(1.), BURN_DAMAGE * 2.);
+
+        // fires only burn for the part of the frame they were still going
+        let left = StatusKind::Burning.duration() - 2.;
+        assert_eq!(status.tick(left + 1.), BURN_DAMAGE * (left + left + 1.));
+        assert!(status.effects.is_empty());
+    }
+
+    #[test]
+    fn thrown_tracks_block_driving() {
+        let mut status = StatusEffects::default();
+        let drive = INPUT_FORWARD | INPUT_LEFT_TRACK_FORWARD | INPUT_RIGHT_TRACK_REVERSE;
+        let other = INPUT_LEFT | INPUT_RIGHT | INPUT_FIRE | 0xffff_ffff_0000_0000;
+        assert_eq!(status.allowed_input(drive | other), drive | other);
+
+        status.apply(StatusKind::Immobilized);
+        assert_eq!(status.allowed_input(drive | other), other);
+        assert_eq!(status.allowed_input(INPUT_REVERSE), 0);
+
+        // a jammed turret or a fire doesn't stop the tank driving
+        let mut status = StatusEffects::default();
+        status.apply(StatusKind::Jammed);
+        status.apply(StatusKind::Burning);
+        assert_eq!(status.allowed_input(drive), drive);
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+
+use crate::ability::AbilityKind;
+use crate::armor::Armor;
+
+/// The kinds of tank a player can take into a match
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TankClass {
+    /// fast scout with a thin hull
+    Light,
+    /// all rounder, the default tank
+    #[default]
+    Medium,
+    /// slow and well protected
+    Heavy,
+    /// casemate gun with a narrow traverse arc
+    TankDestroyer,
+    /// self-propelled gun for indirect fire
+    Spg,
+}
+
+/// Handling numbers shared by every tank of a class
+pub struct TankStats {
+    /// top forward speed in meters per second
+    pub movement_speed: f32,
+    /// top reverse speed in meters per second
+    pub max_reverse_speed: f32,
+    /// meters per second squared
+    pub acceleration: f32,
+    /// meters per second squared
+    pub braking: f32,
+    /// meters per second squared
+    pub rolling_resistance: f32,
+    /// meters per second squared
+    pub lateral_friction: f32,
+    /// hull rotation speed in radians per second
+    pub rotation_speed: f32,
+    /// turret traverse speed in radians per second
+    pub turret_rotation_speed: f32,
+    /// how far the turret can traverse to either side of the hull's forward direction in radians,
+    /// `None` for a turret that can turn all the way around
+    pub traverse_limit: Option<f32>,
+    /// how far the crew can spot enemies in meters
+    pub view_range: f32,
+    /// hit points
+    pub max_health: f32,
+    pub armor: Armor,
+    /// active abilities in slot order
+    pub abilities: &'static [AbilityKind],
+}
+
+impl TankClass {
+    /// Every class in the order the menu cycles through them
+    pub const ALL: [TankClass; 5] = [
+        TankClass::Light,
+        TankClass::Medium,
+        TankClass::Heavy,
+        TankClass::TankDestroyer,
+        TankClass::Spg,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TankClass::Light => "Light",
+            TankClass::Medium => "Medium",
+            TankClass::Heavy => "Heavy",
+            TankClass::TankDestroyer => "Tank destroyer",
+            TankClass::Spg => "SPG",
+        }
+    }
+
+    /// The class after this one in the menu, wrapping around
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&class| class == self).unwrap_or_default();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// The byte a class is sent to the other peers as
+    pub fn to_byte(self) -> u8 {
+        Self::ALL.iter().position(|&class| class == self).unwrap_or_default() as u8
+    }
+
+    /// Reads a class another peer sent, `None` for a byte that isn't one
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(byte)).copied()
+    }
+
+    pub fn stats(self) -> TankStats {
+        match self {
+            TankClass::Light => TankStats {
+                movement_speed: 14.0,
+                max_reverse_speed: 6.0,
+                acceleration: 9.0,
+                braking: 18.0,
+                rolling_resistance: 3.0,
+                lateral_friction: 14.0,
+                rotation_speed: f32::to_radians(220.0),
+                turret_rotation_speed: f32::to_radians(200.0),
+                traverse_limit: None,
+                view_range: 80.0,
+                max_health: 80.0,
+                armor: Armor {
+                    front: 25.0,
+                    side: 15.0,
+                    rear: 10.0,
+                },
+                abilities: &[AbilityKind::Boost, AbilityKind::Smoke],
+            },
+            TankClass::Medium => TankStats {
+                movement_speed: 10.0,
+                max_reverse_speed: 4.0,
+                acceleration: 6.0,
+                braking: 15.0,
+                rolling_resistance: 3.0,
+                lateral_friction: 20.0,
+                rotation_speed: f32::to_radians(180.0),
+                turret_rotation_speed: f32::to_radians(180.0),
+                traverse_limit: None,
+                view_range: 60.0,
+                max_health: 100.0,
+                armor: Armor {
+                    front: 80.0,
+                    side: 45.0,
+                    rear: 30.0,
+                },
+                abilities: &[AbilityKind::Smoke, AbilityKind::Repair],
+            },
+            TankClass::Heavy => TankStats {
+                movement_speed: 7.0,
+                max_reverse_speed: 3.0,
+                acceleration: 4.0,
+                braking: 12.0,
+                rolling_resistance: 4.0,
+                lateral_friction: 26.0,
+                rotation_speed: f32::to_radians(120.0),
+                turret_rotation_speed: f32::to_radians(90.0),
+                traverse_limit: None,
+                view_range: 50.0,
+                max_health: 150.0,
+                armor: Armor {
+                    front: 150.0,
+                    side: 80.0,
+                    rear: 50.0,
+                },
+                abilities: &[AbilityKind::Shield, AbilityKind::Repair],
+            },
+            TankClass::TankDestroyer => TankStats {
+                movement_speed: 9.0,
+                max_reverse_speed: 5.0,
+                acceleration: 5.0,
+                braking: 15.0,
+                rolling_resistance: 3.0,
+                lateral_friction: 20.0,
+                rotation_speed: f32::to_radians(150.0),
+                turret_rotation_speed: f32::to_radians(60.0),
+                traverse_limit: Some(f32::to_radians(15.0)),
+                view_range: 65.0,
+                max_health: 100.0,
+                armor: Armor {
+                    front: 120.0,
+                    side: 40.0,
+                    rear: 25.0,
+                },
+                abilities: &[AbilityKind::Smoke, AbilityKind::Shield],
+            },
+            TankClass::Spg => TankStats {
+                movement_speed: 8.0,
+                max_reverse_speed: 4.0,
+                acceleration: 4.0,
+                braking: 12.0,
+                rolling_resistance: 3.0,
+                lateral_friction: 18.0,
+                rotation_speed: f32::to_radians(120.0),
+                turret_rotation_speed: f32::to_radians(45.0),
+                traverse_limit: Some(f32::to_radians(30.0)),
+                view_range: 45.0,
+                max_health: 70.0,
+                armor: Armor {
+                    front: 30.0,
+                    side: 20.0,
+                    rear: 15.0,
+                },
+                abilities: &[AbilityKind::Smoke],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_survive_being_sent() {
+        for class in TankClass::ALL {
+            assert_eq!(TankClass::from_byte(class.to_byte()), Some(class));
+        }
+        assert_eq!(TankClass::from_byte(TankClass::ALL.len() as u8), None);
+    }
+
+    #[test]
+    fn the_menu_cycles_through_every_class() {
+        let mut class = TankClass::default();
+        let mut seen = vec![class];
+        for _ in 1..TankClass::ALL.len() {
+            class = class.next();
+            assert!(!seen.contains(&class), "{class:?} came up twice");
+            seen.push(class);
+        }
+        assert_eq!(class.next(), TankClass::default());
+    }
+}
@@ -0,0 +1,240 @@
+use bevy::prelude::*;
+
+use crate::map::MapData;
+use crate::MAP_SIZE;
+
+/// Width and height of a single terrain tile in meters
+pub const TILE_SIZE: f32 = 5.;
+/// Number of terrain tiles along each side of the map
+pub const TILES_PER_SIDE: usize = (MAP_SIZE as f32 / TILE_SIZE) as usize;
+
+/// The ground a tile is made of
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TerrainType {
+    #[default]
+    Grass,
+    Road,
+    Mud,
+    Sand,
+    Water,
+    Ice,
+}
+
+/// Multipliers applied to a tank's handling while it drives over a tile
+pub struct TerrainModifiers {
+    /// scales top forward and reverse speed
+    pub max_speed: f32,
+    /// scales how quickly the tank picks up speed
+    pub acceleration: f32,
+    /// scales braking and how well the tracks resist sliding sideways
+    pub traction: f32,
+}
+
+impl TerrainType {
+    pub fn modifiers(self) -> TerrainModifiers {
+        let (max_speed, acceleration, traction) = match self {
+            TerrainType::Grass => (1.0, 1.0, 1.0),
+            TerrainType::Road => (1.3, 1.2, 1.1),
+            TerrainType::Mud => (0.5, 0.5, 1.2),
+            TerrainType::Sand => (0.75, 0.7, 0.8),
+            TerrainType::Water => (0.35, 0.4, 0.6),
+            TerrainType::Ice => (1.0, 0.3, 0.15),
+        };
+        TerrainModifiers {
+            max_speed,
+            acceleration,
+            traction,
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            TerrainType::Grass => Color::rgb(0.53, 0.53, 0.53),
+            TerrainType::Road => Color::rgb(0.35, 0.35, 0.38),
+            TerrainType::Mud => Color::rgb(0.4, 0.3, 0.2),
+            TerrainType::Sand => Color::rgb(0.8, 0.72, 0.5),
+            TerrainType::Water => Color::rgb(0.25, 0.4, 0.7),
+            TerrainType::Ice => Color::rgb(0.8, 0.9, 0.95),
+        }
+    }
+
+    /// Name the map file uses for the terrain
+    pub fn name(self) -> &'static str {
+        match self {
+            TerrainType::Grass => "grass",
+            TerrainType::Road => "road",
+            TerrainType::Mud => "mud",
+            TerrainType::Sand => "sand",
+            TerrainType::Water => "water",
+            TerrainType::Ice => "ice",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "grass" => Some(TerrainType::Grass),
+            "road" => Some(TerrainType::Road),
+            "mud" => Some(TerrainType::Mud),
+            "sand" => Some(TerrainType::Sand),
+            "water" => Some(TerrainType::Water),
+            "ice" => Some(TerrainType::Ice),
+            _ => None,
+        }
+    }
+}
+
+/// Terrain tile layer covering the whole map, tile (0, 0) is the bottom left corner
+#[derive(Clone)]
+pub struct TerrainGrid {
+    tiles: Vec<TerrainType>,
+}
+
+impl Default for TerrainGrid {
+    fn default() -> Self {
+        Self {
+            tiles: vec![TerrainType::default(); TILES_PER_SIDE * TILES_PER_SIDE],
+        }
+    }
+}
+
+impl TerrainGrid {
+    /// Tile coordinates of a world position, `None` when the position is off the map
+    pub fn tile_at(&self, pos: Vec2) -> Option<(usize, usize)> {
+        let local = (pos + Vec2::splat(MAP_SIZE as f32 / 2.)) / TILE_SIZE;
+        if local.x < 0. || local.y < 0. {
+            return None;
+        }
+        let (x, y) = (local.x as usize, local.y as usize);
+        (x < TILES_PER_SIDE && y < TILES_PER_SIDE).then_some((x, y))
+    }
+
+    /// World position of the centre of a tile
+    pub fn tile_center(x: usize, y: usize) -> Vec2 {
+        Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * TILE_SIZE - Vec2::splat(MAP_SIZE as f32 / 2.)
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> TerrainType {
+        self.tiles[y * TILES_PER_SIDE + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, terrain: TerrainType) {
+        self.tiles[y * TILES_PER_SIDE + x] = terrain;
+    }
+
+    /// Fills a rectangle of tiles, clipped to the map
+    pub fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, terrain: TerrainType) {
+        for ty in y..(y + height).min(TILES_PER_SIDE) {
+            for tx in x..(x + width).min(TILES_PER_SIDE) {
+                self.set(tx, ty, terrain);
+            }
+        }
+    }
+
+    /// Terrain under a world position, anything off the map counts as the default terrain
+    pub fn at(&self, pos: Vec2) -> TerrainType {
+        self.tile_at(pos)
+            .map(|(x, y)| self.get(x, y))
+            .unwrap_or_default()
+    }
+
+    /// Horizontal runs of identical non default tiles as `(x, y, length, terrain)`
+    pub fn runs(&self) -> Vec<(usize, usize, usize, TerrainType)> {
+        let mut runs = Vec::new();
+        for y in 0..TILES_PER_SIDE {
+            let mut x = 0;
+            while x < TILES_PER_SIDE {
+                let terrain = self.get(x, y);
+                let start = x;
+                while x < TILES_PER_SIDE && self.get(x, y) == terrain {
+                    x += 1;
+                }
+                if terrain != TerrainType::default() {
+                    runs.push((start, y, x - start, terrain));
+                }
+            }
+        }
+        runs
+    }
+}
+
+/// Marks the sprites drawing the terrain layer
+#[derive(Component)]
+pub struct TerrainTile;
+
+/// Draws the terrain layer underneath the grid, one sprite per run of identical tiles
+pub fn spawn_terrain(
+    mut commands: Commands,
+    map: Res<MapData>,
+) {
+    for (x, y, length, terrain) in map.terrain.runs() {
+        let start = TerrainGrid::tile_center(x, y);
+        let center = start + Vec2::new((length - 1) as f32 * TILE_SIZE / 2., 0.);
+        commands.spawn((
+            SpriteBundle {
+                transform: Transform::from_translation(Vec3::from((center, -2.))),
+                sprite: Sprite {
+                    color: terrain.color(),
+                    custom_size: Some(Vec2::new(length as f32 * TILE_SIZE, TILE_SIZE)),
+                    ..default()
+                },
+                ..default()
+            },
+            TerrainTile,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [TerrainType; 6] = [
+        TerrainType::Grass,
+        TerrainType::Road,
+        TerrainType::Mud,
+        TerrainType::Sand,
+        TerrainType::Water,
+        TerrainType::Ice,
+    ];
+
+    #[test]
+    fn names_round_trip() {
+        let names = ["grass", "road", "mud", "sand", "water", "ice"];
+        for (name, terrain) in names.into_iter().zip(ALL) {
+            assert_eq!(TerrainType::from_name(name), Some(terrain));
+            assert_eq!(terrain.name(), name);
+        }
+        assert_eq!(TerrainType::from_name("lava"), None);
+    }
+
+    #[test]
+    fn tiles_line_up_with_world_positions() {
+        for (x, y) in [(0, 0), (3, 7), (TILES_PER_SIDE - 1, TILES_PER_SIDE - 1)] {
+            assert_eq!(TerrainGrid::default().tile_at(TerrainGrid::tile_center(x, y)), Some((x, y)));
+        }
+        let edge = MAP_SIZE as f32 / 2.;
+        assert_eq!(TerrainGrid::default().tile_at(Vec2::new(-edge - 0.1, 0.)), None);
+        assert_eq!(TerrainGrid::default().tile_at(Vec2::new(0., edge)), None);
+    }
+
+    #[test]
+    fn fill_is_clipped_to_the_map() {
+        let mut grid = TerrainGrid::default();
+        grid.fill(TILES_PER_SIDE - 2, 5, 10, 2, TerrainType::Mud);
+        let x = TILES_PER_SIDE - 2;
+        assert_eq!(grid.runs(), [(x, 5, 2, TerrainType::Mud), (x, 6, 2, TerrainType::Mud)]);
+        assert_eq!(grid.at(TerrainGrid::tile_center(TILES_PER_SIDE - 1, 6)), TerrainType::Mud);
+        assert_eq!(grid.at(Vec2::splat(1e6)), TerrainType::default());
+    }
+
+    #[test]
+    fn runs_skip_the_default_terrain() {
+        let mut grid = TerrainGrid::default();
+        grid.fill(2, 0, 3, 1, TerrainType::Ice);
+        grid.set(3, 0, TerrainType::Road);
+        assert_eq!(
+            grid.runs(),
+            [(2, 0, 1, TerrainType::Ice), (3, 0, 1, TerrainType::Road), (4, 0, 1, TerrainType::Ice)]
+        );
+    }
+}
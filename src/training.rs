@@ -0,0 +1,233 @@
+use bevy::prelude::*;
+use bevy_ggrs::*;
+use std::collections::VecDeque;
+
+use crate::map::MyMapChoice;
+use crate::tank_class::TankClass;
+use crate::{AppState, Config, MyTankClass, MyTankClasses, Player, TankInputs, INPUT_FORWARD, INPUT_LEFT};
+
+/// Seconds of damage the DPS readout averages over
+const DPS_WINDOW: f32 = 5.;
+
+/// Where a target dummy stands, what it drives and whether it drives around
+#[derive(Clone, Copy)]
+pub struct DummyDef {
+    pub position: Vec2,
+    pub class: TankClass,
+    pub moving: bool,
+}
+
+/// The dummies on the training range, one of each class spread out at increasing distances north of the player
+pub const DUMMIES: [DummyDef; 5] = [
+    DummyDef { position: Vec2::new(-10., 30.), class: TankClass::TankDestroyer, moving: false },
+    DummyDef { position: Vec2::new(10., 30.), class: TankClass::Heavy, moving: false },
+    DummyDef { position: Vec2::new(0., 60.), class: TankClass::Light, moving: true },
+    DummyDef { position: Vec2::new(-25., 100.), class: TankClass::Spg, moving: false },
+    DummyDef { position: Vec2::new(25., 100.), class: TankClass::Medium, moving: true },
+];
+
+/// Where the player starts on the training range, the dummies are laid out ahead of it
+pub const RANGE_START: Vec2 = Vec2::ZERO;
+
+/// A tank on the training range that never shoots back
+#[derive(Component)]
+pub struct Dummy {
+    /// whether it drives in circles instead of sitting still
+    pub moving: bool,
+}
+
+/// Cheats for learning a tank on the training range, toggled with I and O
+///
+/// Only the training range offers these, it is played alone so they don't need to be part of the rolled back state.
+#[derive(Resource, Default)]
+pub struct TrainingOptions {
+    /// firing doesn't use up ammo
+    pub infinite_ammo: bool,
+    /// weapons are ready to fire again straight away
+    pub no_reload: bool,
+}
+
+/// Goes straight in-game in the class picked in the menu, there is nobody to wait for on the training range
+///
+/// The dummies are laid out for the arena, so the range is always played there.
+pub fn enter_training_range(
+    mut commands: Commands,
+    my_tank_class: Res<MyTankClass>,
+    mut map_choice: ResMut<MyMapChoice>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    commands.insert_resource(MyTankClasses(vec![my_tank_class.0]));
+    *map_choice = MyMapChoice::Arena;
+    next_state.set(AppState::InGame);
+}
+
+/// Starts an offline session with just the local player, the dummies are driven like bots
+pub fn start_training_session(mut commands: Commands) {
+    let session = ggrs::SessionBuilder::<Config>::new()
+        .with_num_players(1)
+        .with_check_distance(2)
+        .add_player(ggrs::PlayerType::Local, 0)
+        .expect("failed to add player")
+        .start_synctest_session()
+        .expect("failed to start session");
+
+    info!("starting the training range");
+    commands.insert_resource(bevy_ggrs::Session::SyncTest(session));
+}
+
+/// Sends the moving dummies around in circles
+pub fn drive_dummies(
+    mut inputs: ResMut<TankInputs>,
+    dummy_query: Query<(&Player, &Dummy)>,
+) {
+    for (ship, dummy) in &dummy_query {
+        let input = if dummy.moving { INPUT_FORWARD | INPUT_LEFT } else { 0 };
+        inputs.set(ship.handle, input);
+    }
+}
+
+pub fn toggle_training_options(
+    keys: Res<Input<KeyCode>>,
+    mut options: ResMut<TrainingOptions>,
+) {
+    if keys.just_pressed(KeyCode::I) {
+        options.infinite_ammo = !options.infinite_ammo;
+    }
+    if keys.just_pressed(KeyCode::O) {
+        options.no_reload = !options.no_reload;
+    }
+}
+
+/// Damage shells have dealt to the dummies so far, added up where the damage is dealt
+///
+/// It is rolled back with the rest of the simulation, so frames that are simulated again aren't counted twice.
+#[derive(Resource, Clone, Default)]
+pub struct DummyDamage(pub f32);
+
+/// Recent hits on the dummies for the DPS readout
+#[derive(Resource, Default)]
+pub struct DamageMeter {
+    /// dummy damage as of the last frame
+    last_dealt: f32,
+    /// when each hit landed and how much damage it did, oldest first
+    hits: VecDeque<(f32, f32)>,
+    total: f32,
+}
+
+impl DamageMeter {
+    /// Damage per second averaged over the last few seconds
+    fn dps(&self) -> f32 {
+        self.hits.iter().map(|(_, damage)| damage).sum::<f32>() / DPS_WINDOW
+    }
+
+    /// Books whatever the dummy damage total grew by since the last frame
+    fn record(&mut self, now: f32, dealt: f32) {
+        let damage = dealt - self.last_dealt;
+        self.last_dealt = dealt;
+        if damage > 0. {
+            self.hits.push_back((now, damage));
+            self.total += damage;
+        }
+    }
+
+    /// Drops the hits that are too old to count towards the DPS
+    fn forget_old_hits(&mut self, now: f32) {
+        while self.hits.front().is_some_and(|&(at, _)| now - at > DPS_WINDOW) {
+            self.hits.pop_front();
+        }
+    }
+}
+
+/// Books the damage dealt to the dummies since the last frame
+pub fn measure_damage(
+    time: Res<Time>,
+    dealt: Res<DummyDamage>,
+    mut meter: ResMut<DamageMeter>,
+) {
+    let now = time.elapsed_seconds();
+
+    meter.record(now, dealt.0);
+    meter.forget_old_hits(now);
+}
+
+/// Text showing the damage readout and the training options
+#[derive(Component)]
+pub struct TrainingHud;
+
+pub fn spawn_training_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            right: Val::Px(10.),
+            ..default()
+        }),
+        TrainingHud,
+    ));
+}
+
+pub fn update_training_hud(
+    meter: Res<DamageMeter>,
+    options: Res<TrainingOptions>,
+    mut hud_query: Query<&mut Text, With<TrainingHud>>,
+) {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    let value = format!(
+        "DPS: {:.0}\nTotal damage: {:.0}\nI infinite ammo: {}\nO no reload: {}",
+        meter.dps(),
+        meter.total,
+        on_off(options.infinite_ammo),
+        on_off(options.no_reload),
+    );
+
+    for mut text in &mut hud_query {
+        text.sections[0].value.clone_from(&value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dps_averages_the_damage_over_the_window() {
+        let mut meter = DamageMeter::default();
+        meter.record(0., 0.);
+        assert_eq!(meter.dps(), 0.);
+
+        meter.record(1., 20.);
+        meter.record(2., 50.);
+        assert_eq!(meter.dps(), 50. / DPS_WINDOW);
+        assert_eq!(meter.total, 50.);
+    }
+
+    #[test]
+    fn frames_without_new_damage_book_nothing() {
+        let mut meter = DamageMeter::default();
+        meter.record(1., 30.);
+        meter.record(2., 30.);
+        assert_eq!(meter.hits.len(), 1);
+        assert_eq!(meter.total, 30.);
+    }
+
+    #[test]
+    fn old_hits_drop_out_of_the_dps() {
+        let mut meter = DamageMeter::default();
+        meter.record(1., 10.);
+        meter.record(4., 30.);
+
+        meter.forget_old_hits(1. + DPS_WINDOW);
+        assert_eq!(meter.dps(), 30. / DPS_WINDOW);
+        meter.forget_old_hits(4.5 + DPS_WINDOW);
+        assert_eq!(meter.dps(), 0.);
+        assert_eq!(meter.total, 30., "the total keeps every hit");
+    }
+}
@@ -0,0 +1,255 @@
+use bevy::prelude::*;
+
+use crate::bot::{Bot, Difficulty};
+use crate::health::Health;
+use crate::module::Modules;
+use crate::status::StatusEffects;
+use crate::weapon::Armament;
+use crate::{Player, Team};
+
+/// Size of the pool of bots the waves are drawn from
+pub const WAVE_BOTS: usize = 8;
+/// The team of the players and the bots filling in for missing players
+pub const PLAYER_TEAM: Team = Team(0);
+/// The team of the bots the waves are drawn from
+pub const ENEMY_TEAM: Team = Team(1);
+/// Seconds of breathing room before the first wave and between waves
+const INTERMISSION: f32 = 10.;
+/// Bots in the first wave, every wave after brings one more
+const FIRST_WAVE_SIZE: usize = 2;
+/// Points for each enemy destroyed, multiplied by the wave number
+const KILL_SCORE: u32 = 100;
+/// Points for clearing a wave, multiplied by the wave number
+const WAVE_BONUS: u32 = 500;
+/// Gap between bot spawn points along the north edge in meters
+const BOT_SPAWN_SPACING: f32 = 8.;
+/// How far in from the north edge of the playable area the bots come in from in meters
+const BOT_SPAWN_INSET: f32 = 15.;
+
+/// Where a co-op match is at
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WavePhase {
+    /// seconds left until the next wave rolls in
+    Intermission(f32),
+    Fighting,
+    /// every player was destroyed during a wave
+    GameOver,
+}
+
+/// Progress of a co-op match, rolled back so every peer agrees on the wave, the enemies and the score
+#[derive(Resource, Clone)]
+pub struct WaveState {
+    /// the current wave, zero before the first one
+    pub wave: u32,
+    pub phase: WavePhase,
+    pub score: u32,
+    /// enemies left standing at the end of the last frame
+    pub enemies: usize,
+}
+
+impl Default for WaveState {
+    fn default() -> Self {
+        Self {
+            wave: 0,
+            phase: WavePhase::Intermission(INTERMISSION),
+            score: 0,
+            enemies: 0,
+        }
+    }
+}
+
+/// Number of bots in a wave
+fn wave_size(wave: u32) -> usize {
+    (FIRST_WAVE_SIZE + wave as usize - 1).min(WAVE_BOTS)
+}
+
+/// How good the bots in a wave are
+fn wave_difficulty(wave: u32) -> Difficulty {
+    match wave {
+        0..=2 => Difficulty::Easy,
+        3..=5 => Difficulty::Normal,
+        _ => Difficulty::Hard,
+    }
+}
+
+/// Where the bots in the pool come in, spread out along the north edge of a playable area `bounds` across
+pub fn bot_spawn_points(bounds: Vec2) -> Vec<Vec2> {
+    let y = bounds.y / 2. - BOT_SPAWN_INSET;
+    (0..WAVE_BOTS)
+        .map(|index| Vec2::new((index as f32 - (WAVE_BOTS - 1) as f32 / 2.) * BOT_SPAWN_SPACING, y))
+        .collect()
+}
+
+/// Sends in the waves, scores the kills, patches the players up between waves and ends the match when every player
+/// is down
+///
+/// Tanks only come back when this lets them: enemies when their wave starts and the players' team once a wave is
+/// cleared.
+#[allow(clippy::type_complexity)]
+pub fn run_waves(
+    time: Res<Time>,
+    mut waves: ResMut<WaveState>,
+    mut tank_query: Query<(
+        &Player,
+        &Team,
+        &mut Health,
+        Option<&mut Bot>,
+        &mut Modules,
+        &mut Armament,
+        &mut StatusEffects,
+    )>,
+) {
+    for (_, _, mut health, _, _, _, _) in &mut tank_query {
+        health.respawn = false;
+    }
+
+    let players_alive = tank_query
+        .iter()
+        .filter(|(_, team, health, ..)| **team == PLAYER_TEAM && health.current > 0.)
+        .count();
+    let enemies_alive = tank_query
+        .iter()
+        .filter(|(_, team, health, ..)| **team == ENEMY_TEAM && health.current > 0.)
+        .count();
+
+    match waves.phase {
+        WavePhase::Fighting => {
+            let destroyed = waves.enemies.saturating_sub(enemies_alive) as u32;
+            waves.score += destroyed * KILL_SCORE * waves.wave;
+            waves.enemies = enemies_alive;
+
+            if players_alive == 0 {
+                waves.phase = WavePhase::GameOver;
+                info!("game over on wave {} with {} points", waves.wave, waves.score);
+            } else if enemies_alive == 0 {
+                waves.score += WAVE_BONUS * waves.wave;
+                waves.phase = WavePhase::Intermission(INTERMISSION);
+
+                // everyone who made it gets patched up and rearmed, everyone who didn't comes back
+                for (_, team, mut health, _, mut modules, mut armament, mut status) in &mut tank_query {
+                    if *team != PLAYER_TEAM {
+                        continue;
+                    }
+                    if health.current > 0. {
+                        health.current = health.max;
+                        *modules = Modules::default();
+                        *armament = Armament::default();
+                        *status = StatusEffects::default();
+                    } else {
+                        health.respawn = true;
+                    }
+                }
+            }
+        }
+        WavePhase::Intermission(remaining) => {
+            let remaining = remaining - time.delta_seconds();
+            if remaining > 0. {
+                waves.phase = WavePhase::Intermission(remaining);
+                return;
+            }
+
+            waves.wave += 1;
+            waves.phase = WavePhase::Fighting;
+            let size = wave_size(waves.wave);
+            let difficulty = wave_difficulty(waves.wave);
+
+            // the lowest handles in the pool go first so every peer sends in the same bots
+            let mut pool: Vec<_> = tank_query
+                .iter_mut()
+                .filter(|(_, team, ..)| **team == ENEMY_TEAM)
+                .filter_map(|(ship, _, health, bot, ..)| bot.map(|bot| (ship.handle, health, bot)))
+                .collect();
+            pool.sort_by_key(|(handle, _, _)| *handle);
+            for (_, mut health, mut bot) in pool.into_iter().take(size) {
+                *bot = Bot::new(difficulty);
+                health.respawn = true;
+            }
+            waves.enemies = size;
+        }
+        WavePhase::GameOver => {}
+    }
+}
+
+/// Text showing the wave, the enemies left and the score
+#[derive(Component)]
+pub struct WaveHud;
+
+pub fn spawn_wave_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 28.,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.),
+            left: Val::Percent(40.),
+            ..default()
+        }),
+        WaveHud,
+    ));
+}
+
+pub fn update_wave_hud(
+    waves: Res<WaveState>,
+    mut hud_query: Query<&mut Text, With<WaveHud>>,
+) {
+    let status = match waves.phase {
+        WavePhase::Intermission(remaining) => format!("Wave {} in {:.0}s", waves.wave + 1, remaining.ceil()),
+        WavePhase::Fighting => format!("Wave {}: {} enemies left", waves.wave, waves.enemies),
+        WavePhase::GameOver => format!("Game over on wave {}, final score {}", waves.wave, waves.score),
+    };
+    let value = match waves.phase {
+        WavePhase::GameOver => status,
+        _ => format!("{status}   Score {}", waves.score),
+    };
+
+    for mut text in &mut hud_query {
+        text.sections[0].value.clone_from(&value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_wave_brings_one_more_bot() {
+        assert_eq!(wave_size(1), FIRST_WAVE_SIZE);
+        for wave in 1..5 {
+            assert_eq!(wave_size(wave + 1), wave_size(wave) + 1);
+        }
+    }
+
+    #[test]
+    fn waves_never_outgrow_the_pool() {
+        assert_eq!(wave_size(WAVE_BOTS as u32), WAVE_BOTS);
+        assert_eq!(wave_size(100), WAVE_BOTS);
+    }
+
+    #[test]
+    fn later_waves_bring_better_bots() {
+        assert_eq!(wave_difficulty(1), Difficulty::Easy);
+        assert_eq!(wave_difficulty(2), Difficulty::Easy);
+        assert_eq!(wave_difficulty(3), Difficulty::Normal);
+        assert_eq!(wave_difficulty(5), Difficulty::Normal);
+        assert_eq!(wave_difficulty(6), Difficulty::Hard);
+        assert_eq!(wave_difficulty(100), Difficulty::Hard);
+    }
+
+    #[test]
+    fn bots_come_in_spread_along_the_north_edge() {
+        let bounds = Vec2::new(400., 300.);
+        let points = bot_spawn_points(bounds);
+        assert_eq!(points.len(), WAVE_BOTS);
+        assert_eq!(points[0].x, -points[WAVE_BOTS - 1].x, "the line is centred on the arena");
+        for pair in points.windows(2) {
+            assert_eq!(pair[1] - pair[0], Vec2::new(BOT_SPAWN_SPACING, 0.));
+        }
+        assert!(points.iter().all(|point| point.y == bounds.y / 2. - BOT_SPAWN_INSET));
+    }
+}
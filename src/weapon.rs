@@ -0,0 +1,273 @@
+use bevy::prelude::*;
+use bevy_ggrs::*;
+
+use crate::Player;
+
+/// Bits of the input holding the selected weapon slot
+pub const INPUT_WEAPON_SHIFT: u64 = 9;
+pub const INPUT_WEAPON_MASK: u64 = 0b11 << INPUT_WEAPON_SHIFT;
+
+/// The weapons every tank carries, in slot order
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum WeaponKind {
+    #[default]
+    Cannon,
+    MachineGun,
+    Artillery,
+    Mines,
+}
+
+pub const WEAPONS: [WeaponKind; 4] = [
+    WeaponKind::Cannon,
+    WeaponKind::MachineGun,
+    WeaponKind::Artillery,
+    WeaponKind::Mines,
+];
+
+/// How a weapon behaves
+pub struct WeaponDef {
+    pub name: &'static str,
+    pub damage: f32,
+    /// millimetres of armor a shot can go through head on
+    pub penetration: f32,
+    /// seconds between shots
+    pub fire_interval: f32,
+    /// meters per second, zero for weapons that are dropped rather than fired
+    pub projectile_speed: f32,
+    /// largest angle in radians a shot can stray to either side of where the turret points
+    pub spread: f32,
+    /// meters around the impact that take damage, zero for weapons that only hit what they touch
+    pub splash_radius: f32,
+    /// meters a shot travels before it falls harmlessly
+    pub range: f32,
+    /// seconds after leaving the tank before a shot can go off
+    pub arming_time: f32,
+    /// most shots one tank can have out at once, used to stop the map filling up with mines
+    pub max_deployed: Option<usize>,
+    /// rounds carried into battle
+    pub ammo: u32,
+    /// lobbed over obstacles to land on the aim point instead of flying straight from the muzzle
+    pub indirect: bool,
+    pub color: Color,
+    pub size: Vec2,
+}
+
+impl WeaponDef {
+    /// Whether another shot can go out with `deployed` of this weapon's shots from the same tank still around
+    pub fn can_deploy(&self, deployed: usize) -> bool {
+        self.max_deployed.is_none_or(|max_deployed| deployed < max_deployed)
+    }
+}
+
+impl WeaponKind {
+    pub fn def(self) -> WeaponDef {
+        match self {
+            WeaponKind::Cannon => WeaponDef {
+                name: "Cannon",
+                damage: 25.,
+                penetration: 120.,
+                fire_interval: 1.5,
+                projectile_speed: 60.,
+                spread: f32::to_radians(0.5),
+                splash_radius: 0.,
+                range: 150.,
+                arming_time: 0.,
+                max_deployed: None,
+                ammo: 40,
+                indirect: false,
+                color: Color::ORANGE,
+                size: Vec2::new(0.3, 0.8),
+            },
+            WeaponKind::MachineGun => WeaponDef {
+                name: "Machine gun",
+                damage: 4.,
+                penetration: 20.,
+                fire_interval: 0.1,
+                projectile_speed: 80.,
+                spread: f32::to_radians(3.),
+                splash_radius: 0.,
+                range: 60.,
+                arming_time: 0.,
+                max_deployed: None,
+                ammo: 300,
+                indirect: false,
+                color: Color::YELLOW,
+                size: Vec2::new(0.1, 0.4),
+            },
+            WeaponKind::Artillery => WeaponDef {
+                name: "Artillery",
+                damage: 40.,
+                penetration: 40.,
+                fire_interval: 4.,
+                projectile_speed: 40.,
+                spread: f32::to_radians(2.),
+                splash_radius: 5.,
+                range: 200.,
+                arming_time: 0.,
+                max_deployed: None,
+                ammo: 15,
+                indirect: true,
+                color: Color::ORANGE_RED,
+                size: Vec2::new(0.5, 0.5),
+            },
+            WeaponKind::Mines => WeaponDef {
+                name: "Mines",
+                damage: 50.,
+                penetration: 0.,
+                fire_interval: 2.,
+                projectile_speed: 0.,
+                spread: 0.,
+                splash_radius: 3.,
+                range: f32::INFINITY,
+                arming_time: 1.5,
+                max_deployed: Some(3),
+                ammo: 5,
+                indirect: false,
+                color: Color::DARK_GRAY,
+                size: Vec2::new(0.8, 0.8),
+            },
+        }
+    }
+
+    pub fn slot(self) -> usize {
+        WEAPONS.iter().position(|&weapon| weapon == self).unwrap_or_default()
+    }
+
+    /// The weapon selected by an input
+    pub fn from_input(input: u64) -> Self {
+        WEAPONS[((input & INPUT_WEAPON_MASK) >> INPUT_WEAPON_SHIFT) as usize]
+    }
+
+    /// The input bits selecting this weapon
+    pub fn to_input(self) -> u64 {
+        (self.slot() as u64) << INPUT_WEAPON_SHIFT
+    }
+}
+
+/// A tank's weapons, rolled back so the active weapon, reload and ammo agree on every peer
+#[derive(Component, Clone)]
+pub struct Armament {
+    pub active: WeaponKind,
+    /// seconds until each weapon slot can fire again, every weapon reloads on its own
+    pub cooldowns: [f32; WEAPONS.len()],
+    /// rounds left for each weapon slot
+    pub ammo: [u32; WEAPONS.len()],
+}
+
+impl Default for Armament {
+    fn default() -> Self {
+        Self {
+            active: WeaponKind::default(),
+            cooldowns: [0.; WEAPONS.len()],
+            ammo: WEAPONS.map(|weapon| weapon.def().ammo),
+        }
+    }
+}
+
+/// The weapon the local player has selected
+#[derive(Resource, Default)]
+pub struct MySelectedWeapon(pub WeaponKind);
+
+/// Selects a weapon with the number keys
+pub fn select_weapon(
+    keys: Res<Input<KeyCode>>,
+    mut selected: ResMut<MySelectedWeapon>,
+) {
+    for (key, weapon) in [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4]
+        .into_iter()
+        .zip(WEAPONS)
+    {
+        if keys.just_pressed(key) {
+            selected.0 = weapon;
+        }
+    }
+}
+
+/// Text showing the local player's active weapon and ammo
+#[derive(Component)]
+pub struct WeaponHud;
+
+pub fn spawn_weapon_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.),
+            right: Val::Px(10.),
+            ..default()
+        }),
+        WeaponHud,
+    ));
+}
+
+pub fn update_weapon_hud(
+    local_players: Option<Res<LocalPlayers>>,
+    selected: Res<MySelectedWeapon>,
+    armament_query: Query<(&Player, &Armament)>,
+    mut hud_query: Query<&mut Text, With<WeaponHud>>,
+) {
+    let local_handles = local_players.map(|local_players| local_players.0.clone()).unwrap_or_default();
+
+    let mut lines = Vec::new();
+    for (ship, armament) in &armament_query {
+        if !local_handles.contains(&ship.handle) {
+            continue;
+        }
+        for weapon in WEAPONS {
+            let marker = if weapon == armament.active { ">" } else { " " };
+            let ammo = armament.ammo[weapon.slot()];
+            lines.push(format!("{marker} {} {}: {ammo}", weapon.slot() + 1, weapon.def().name));
+        }
+    }
+    if lines.is_empty() {
+        lines.push(format!("{} selected", selected.0.def().name));
+    }
+
+    for mut text in &mut hud_query {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weapon_round_trips_through_the_input() {
+        for weapon in WEAPONS {
+            assert_eq!(WeaponKind::from_input(weapon.to_input()), weapon);
+            assert_eq!(weapon.to_input() & !INPUT_WEAPON_MASK, 0);
+        }
+    }
+
+    #[test]
+    fn weapon_ignores_the_other_input_bits() {
+        let other = !INPUT_WEAPON_MASK;
+        assert_eq!(WeaponKind::from_input(other), WeaponKind::Cannon);
+        assert_eq!(WeaponKind::from_input(other | WeaponKind::Mines.to_input()), WeaponKind::Mines);
+    }
+
+    #[test]
+    fn mines_are_limited_per_tank() {
+        let mines = WeaponKind::Mines.def();
+        let limit = mines.max_deployed.unwrap();
+        assert!(mines.can_deploy(limit - 1));
+        assert!(!mines.can_deploy(limit));
+        assert!(WeaponKind::Cannon.def().can_deploy(usize::MAX));
+    }
+
+    #[test]
+    fn every_slot_is_a_weapon() {
+        assert_eq!(WEAPONS.len(), (INPUT_WEAPON_MASK >> INPUT_WEAPON_SHIFT) as usize + 1);
+        for (slot, weapon) in WEAPONS.into_iter().enumerate() {
+            assert_eq!(weapon.slot(), slot);
+        }
+    }
+}